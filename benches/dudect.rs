@@ -0,0 +1,159 @@
+// A dudect-style check (https://eprint.iacr.org/2016/1123) that the time of
+// every permutation the sponge can be built on doesn't depend on the state:
+// Poseidon and Poseidon2 over K256 with the width 3, and Rescue-Prime Optimized
+// over Goldilocks. It compares the timing
+// distributions of a fixed state and of random states with Welch's t-test, on
+// the measurements cropped at increasing percentiles. A |t| above 10 means the
+// time depends on the state, and one below 4.5 that no dependence was found.
+//
+// Run it on an otherwise idle machine with `cargo bench --bench dudect`, and
+// more measurements with `cargo bench --bench dudect -- <number>`.
+use ff::Field;
+use halo2curves::secp256k1::Fp;
+use poseidon_transcript::goldilocks::Goldilocks;
+use poseidon_transcript::poseidon::Poseidon;
+use poseidon_transcript::poseidon2::Poseidon2;
+use poseidon_transcript::rescue::{self, RescuePrime};
+use poseidon_transcript::spec::{PoseidonSpec, K256};
+use rand::Rng;
+use std::hint::black_box;
+use std::time::Instant;
+
+const DEFAULT_NUM_MEASUREMENTS: usize = 200_000;
+const NUM_PERCENTILES: usize = 100;
+const LEAK_THRESHOLD: f64 = 10.0;
+
+// Welch's t-test of two classes, with the means and variances updated online
+#[derive(Default)]
+struct TTest {
+    count: [f64; 2],
+    mean: [f64; 2],
+    m2: [f64; 2],
+}
+
+impl TTest {
+    fn push(&mut self, class: usize, x: f64) {
+        self.count[class] += 1.0;
+        let delta = x - self.mean[class];
+        self.mean[class] += delta / self.count[class];
+        self.m2[class] += delta * (x - self.mean[class]);
+    }
+
+    fn t(&self) -> f64 {
+        let variance = |class: usize| self.m2[class] / (self.count[class] - 1.0);
+
+        (self.mean[0] - self.mean[1])
+            / (variance(0) / self.count[0] + variance(1) / self.count[1]).sqrt()
+    }
+}
+
+// The largest |t| between the fixed class 0 and the random class 1, measured in
+// a random order
+fn max_t<F: Field, const T: usize>(
+    num_measurements: usize,
+    mut permute: impl FnMut([F; T]) -> [F; T],
+) -> f64 {
+    let mut rng = rand::thread_rng();
+
+    let inputs: Vec<(usize, [F; T])> = (0..num_measurements)
+        .map(|_| {
+            let class = rng.gen_range(0..2);
+            let state = if class == 0 {
+                [F::zero(); T]
+            } else {
+                [(); T].map(|_| F::random(&mut rng))
+            };
+            (class, state)
+        })
+        .collect();
+
+    let timings: Vec<(usize, f64)> = inputs
+        .into_iter()
+        .map(|(class, state)| {
+            let start = Instant::now();
+            black_box(permute(black_box(state)));
+            (class, start.elapsed().as_nanos() as f64)
+        })
+        .collect();
+
+    // Crop the measurements above percentiles that get closer to 100, since
+    // interrupts and cache misses only give large outliers
+    let mut sorted: Vec<f64> = timings.iter().map(|(_, timing)| *timing).collect();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let thresholds = (0..NUM_PERCENTILES)
+        .map(|i| {
+            let percentile = 1.0 - 0.5f64.powf(10.0 * (i + 1) as f64 / NUM_PERCENTILES as f64);
+            sorted[(percentile * (sorted.len() - 1) as f64) as usize]
+        })
+        .chain(std::iter::once(f64::INFINITY));
+
+    thresholds
+        .map(|threshold| {
+            let mut ttest = TTest::default();
+            for (class, timing) in timings.iter() {
+                if *timing <= threshold {
+                    ttest.push(*class, *timing);
+                }
+            }
+            ttest.t().abs()
+        })
+        .fold(0.0, f64::max)
+}
+
+fn main() {
+    // `cargo bench` passes --bench to the harness
+    let num_measurements = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_NUM_MEASUREMENTS);
+
+    let mut poseidon = Poseidon::new(K256::constants(), [Fp::zero(); 3]);
+    let mut poseidon2 = Poseidon2::new(K256::poseidon2_constants().unwrap(), [Fp::zero(); 3]);
+    let mut rescue_prime =
+        RescuePrime::new(rescue::constants(), [Goldilocks::zero(); rescue::WIDTH]);
+
+    let results = [
+        (
+            "poseidon",
+            max_t(num_measurements, |state| {
+                poseidon.state = state;
+                poseidon.permute();
+                poseidon.state
+            }),
+        ),
+        (
+            "poseidon2",
+            max_t(num_measurements, |state| {
+                poseidon2.state = state;
+                poseidon2.permute();
+                poseidon2.state
+            }),
+        ),
+        (
+            "rescue-prime",
+            max_t(num_measurements, |state| {
+                rescue_prime.state = state;
+                rescue_prime.permute();
+                rescue_prime.state
+            }),
+        ),
+    ];
+
+    let mut leaks = false;
+    for (name, t) in results {
+        let verdict = if t > LEAK_THRESHOLD {
+            leaks = true;
+            "the time depends on the state"
+        } else {
+            "no dependence found"
+        };
+        println!(
+            "{}: max |t| = {:.2} over {} measurements, {}",
+            name, t, num_measurements, verdict
+        );
+    }
+
+    if leaks {
+        std::process::exit(1);
+    }
+}
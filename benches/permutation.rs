@@ -0,0 +1,162 @@
+// Compares the permutation on a fixed-size array state with the previous design,
+// which kept the state in a Vec and allocated a new Vec for every matrix
+// multiplication.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use ff::PrimeField;
+use halo2curves::secp256k1::Fp;
+use poseidon_transcript::poseidon::{
+    generate_constants, permute_batch, Poseidon, PoseidonConstants,
+};
+use std::sync::Arc;
+
+// The permutation before the state became an array
+struct VecPoseidon<F: PrimeField, const T: usize> {
+    state: Vec<F>,
+    constants: Arc<PoseidonConstants<F, T>>,
+    pos: usize,
+}
+
+impl<F: PrimeField, const T: usize> VecPoseidon<F, T> {
+    fn permute(&mut self) {
+        let full_rounds_half = self.constants.num_full_rounds / 2;
+
+        for _ in 0..full_rounds_half {
+            self.full_round();
+        }
+
+        self.partial_rounds();
+
+        for _ in 0..full_rounds_half {
+            self.full_round();
+        }
+
+        self.pos = 0;
+    }
+
+    fn full_round(&mut self) {
+        for i in 0..self.state.len() {
+            self.state[i] += self.constants.round_keys[i + self.pos];
+            self.state[i] = self.state[i].pow_vartime([5, 0, 0, 0]);
+        }
+
+        self.state = matrix_mul(&self.constants.mds_matrix, &self.state);
+
+        self.pos += self.state.len();
+    }
+
+    fn partial_rounds(&mut self) {
+        let optimized = &self.constants.optimized;
+
+        for (x, c) in self
+            .state
+            .iter_mut()
+            .zip(optimized.first_partial_round_keys.iter())
+        {
+            *x += c;
+        }
+
+        self.state = matrix_mul(&optimized.pre_sparse_matrix, &self.state);
+
+        for (i, sparse_matrix) in optimized.sparse_matrices.iter().enumerate() {
+            self.state[0] = self.state[0].pow_vartime([5, 0, 0, 0]);
+
+            if let Some(c) = optimized.partial_round_keys.get(i) {
+                self.state[0] += c;
+            }
+
+            let s_0 = self.state[0];
+            let mut first = sparse_matrix.m_00 * s_0;
+            for (x, v) in self.state[1..].iter().zip(sparse_matrix.row.iter()) {
+                first += *x * v;
+            }
+            for (x, w) in self.state[1..].iter_mut().zip(sparse_matrix.col.iter()) {
+                *x += *w * s_0;
+            }
+            self.state[0] = first;
+        }
+
+        self.pos += self.constants.num_partial_rounds * self.state.len();
+    }
+}
+
+fn matrix_mul<F: PrimeField, const T: usize>(matrix: &[[F; T]; T], state: &[F]) -> Vec<F> {
+    let mut result = Vec::new();
+
+    for val in matrix.iter() {
+        let mut tmp = F::zero();
+        for (j, element) in state.iter().enumerate() {
+            tmp += val[j] * element
+        }
+        result.push(tmp)
+    }
+
+    result
+}
+
+fn bench_width<const T: usize>(c: &mut Criterion, num_partial_rounds: usize) {
+    let constants = Arc::new(generate_constants::<Fp, T>(5, 8, num_partial_rounds));
+    let mut group = c.benchmark_group("permutation");
+
+    group.bench_function(BenchmarkId::new("vec", T), |b| {
+        let mut poseidon = VecPoseidon {
+            state: vec![Fp::zero(); T],
+            constants: constants.clone(),
+            pos: 0,
+        };
+        b.iter(|| {
+            poseidon.permute();
+            black_box(&poseidon.state);
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("array", T), |b| {
+        let mut poseidon = Poseidon::new(constants.clone(), [Fp::zero(); T]);
+        b.iter(|| {
+            poseidon.permute();
+            black_box(&poseidon.state);
+        })
+    });
+
+    group.finish();
+}
+
+// Round numbers of the K256 parameter sets
+fn bench_permutation(c: &mut Criterion) {
+    bench_width::<3>(c, 56);
+    bench_width::<5>(c, 57);
+    bench_width::<9>(c, 57);
+}
+
+// Permuting 1024 states one at a time and with `permute_batch`
+fn bench_batch(c: &mut Criterion) {
+    const NUM_STATES: usize = 1024;
+
+    let constants = Arc::new(generate_constants::<Fp, 3>(5, 8, 56));
+    let mut group = c.benchmark_group("batch");
+
+    group.bench_function(BenchmarkId::new("one_at_a_time", NUM_STATES), |b| {
+        let mut poseidon = Poseidon::new(constants.clone(), [Fp::zero(); 3]);
+        let mut states = vec![[Fp::zero(); 3]; NUM_STATES];
+        b.iter(|| {
+            for state in states.iter_mut() {
+                poseidon.state = *state;
+                poseidon.permute();
+                *state = poseidon.state;
+            }
+            black_box(&states);
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("permute_batch", NUM_STATES), |b| {
+        let mut states = vec![[Fp::zero(); 3]; NUM_STATES];
+        b.iter(|| {
+            permute_batch(&constants, &mut states);
+            black_box(&states);
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_permutation, bench_batch);
+criterion_main!(benches);
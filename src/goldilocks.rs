@@ -0,0 +1,318 @@
+// The Goldilocks field of the prime p = 2^64 - 2^32 + 1, over which
+// Rescue-Prime Optimized is defined. Elements are kept as their canonical u64,
+// and every operation is constant time except `sqrt`.
+use ff::{Field, PrimeField};
+use rand_core::RngCore;
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq, CtOption};
+
+const MODULUS: u64 = 0xffff_ffff_0000_0001;
+
+// 2^64 mod p
+const EPSILON: u64 = 0xffff_ffff;
+
+/// An element of the Goldilocks field. The encoding is the 8 little-endian bytes
+/// of the canonical integer.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Goldilocks(u64);
+
+impl Goldilocks {
+    /// The element of a canonical integer, if it's less than p.
+    pub const fn from_canonical_u64(x: u64) -> Option<Self> {
+        if x < MODULUS {
+            Some(Self(x))
+        } else {
+            None
+        }
+    }
+
+    /// The canonical integer of the element.
+    pub const fn to_canonical_u64(&self) -> u64 {
+        self.0
+    }
+
+    // x if the bit is set, and zero otherwise
+    fn select(bit: bool, x: u64) -> u64 {
+        u64::conditional_select(&0, &x, Choice::from(bit as u8))
+    }
+
+    // x - p if x >= p, for any u64
+    fn reduce_once(x: u64) -> Self {
+        let (reduced, borrow) = x.overflowing_sub(MODULUS);
+        Self(u64::conditional_select(
+            &reduced,
+            &x,
+            Choice::from(borrow as u8),
+        ))
+    }
+
+    // x mod p, with 2^64 = 2^32 - 1 and 2^96 = -1 mod p
+    fn reduce_u128(x: u128) -> Self {
+        let low = x as u64;
+        let high = (x >> 64) as u64;
+        let high_high = high >> 32;
+        let high_low = high & EPSILON;
+
+        let (t0, borrow) = low.overflowing_sub(high_high);
+        let t0 = t0.wrapping_sub(Self::select(borrow, EPSILON));
+        let t1 = high_low * EPSILON;
+        let (t2, carry) = t0.overflowing_add(t1);
+
+        Self::reduce_once(t2.wrapping_add(Self::select(carry, EPSILON)))
+    }
+
+    // x^exponent with a sequence of operations that only depends on the exponent
+    fn pow(&self, exponent: u64) -> Self {
+        (0..64).rev().fold(Self::one(), |acc, i| {
+            let acc = acc.square();
+            if (exponent >> i) & 1 == 1 {
+                acc * self
+            } else {
+                acc
+            }
+        })
+    }
+}
+
+impl fmt::Debug for Goldilocks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#018x}", self.0)
+    }
+}
+
+impl From<u64> for Goldilocks {
+    fn from(x: u64) -> Self {
+        Self::reduce_once(x)
+    }
+}
+
+impl ConditionallySelectable for Goldilocks {
+    fn conditional_select(a: &Self, b: &Self, choice: Choice) -> Self {
+        Self(u64::conditional_select(&a.0, &b.0, choice))
+    }
+}
+
+impl ConstantTimeEq for Goldilocks {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl<'a> Add<&'a Goldilocks> for Goldilocks {
+    type Output = Goldilocks;
+
+    fn add(self, rhs: &'a Goldilocks) -> Goldilocks {
+        // The sum is below 2p, and adding 2^64 mod p after an overflow can't
+        // overflow again
+        let (sum, carry) = self.0.overflowing_add(rhs.0);
+        Self::reduce_once(sum.wrapping_add(Self::select(carry, EPSILON)))
+    }
+}
+
+impl<'a> Sub<&'a Goldilocks> for Goldilocks {
+    type Output = Goldilocks;
+
+    fn sub(self, rhs: &'a Goldilocks) -> Goldilocks {
+        let (difference, borrow) = self.0.overflowing_sub(rhs.0);
+        Self(difference.wrapping_add(Self::select(borrow, MODULUS)))
+    }
+}
+
+impl<'a> Mul<&'a Goldilocks> for Goldilocks {
+    type Output = Goldilocks;
+
+    fn mul(self, rhs: &'a Goldilocks) -> Goldilocks {
+        Self::reduce_u128(self.0 as u128 * rhs.0 as u128)
+    }
+}
+
+impl Neg for Goldilocks {
+    type Output = Goldilocks;
+
+    fn neg(self) -> Goldilocks {
+        Goldilocks::zero() - self
+    }
+}
+
+macro_rules! impl_ops {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident) => {
+        impl $trait for Goldilocks {
+            type Output = Goldilocks;
+
+            fn $method(self, rhs: Goldilocks) -> Goldilocks {
+                self.$method(&rhs)
+            }
+        }
+
+        impl $assign_trait for Goldilocks {
+            fn $assign_method(&mut self, rhs: Goldilocks) {
+                *self = self.$method(&rhs);
+            }
+        }
+
+        impl<'a> $assign_trait<&'a Goldilocks> for Goldilocks {
+            fn $assign_method(&mut self, rhs: &'a Goldilocks) {
+                *self = self.$method(rhs);
+            }
+        }
+    };
+}
+
+impl_ops!(Add, add, AddAssign, add_assign);
+impl_ops!(Sub, sub, SubAssign, sub_assign);
+impl_ops!(Mul, mul, MulAssign, mul_assign);
+
+impl Field for Goldilocks {
+    fn random(mut rng: impl RngCore) -> Self {
+        loop {
+            if let Some(x) = Self::from_canonical_u64(rng.next_u64()) {
+                return x;
+            }
+        }
+    }
+
+    fn zero() -> Self {
+        Self(0)
+    }
+
+    fn one() -> Self {
+        Self(1)
+    }
+
+    fn square(&self) -> Self {
+        *self * self
+    }
+
+    fn double(&self) -> Self {
+        *self + self
+    }
+
+    fn invert(&self) -> CtOption<Self> {
+        // x^(p - 2)
+        CtOption::new(self.pow(MODULUS - 2), !self.is_zero())
+    }
+
+    // Tonelli-Shanks, whose number of operations depends on x
+    fn sqrt(&self) -> CtOption<Self> {
+        // p - 1 = 2^32 * (2^32 - 1)
+        let mut z = Self::root_of_unity();
+        let mut m = Self::S;
+        let mut c = self.pow(EPSILON);
+        let mut r = self.pow(EPSILON / 2 + 1);
+
+        while c != Self::one() && !bool::from(c.is_zero()) {
+            let mut i = 0;
+            let mut c_2i = c;
+            while c_2i != Self::one() {
+                c_2i = c_2i.square();
+                i += 1;
+            }
+
+            // A non-square reaches -1 only after m squarings
+            if i == m {
+                return CtOption::new(Self::zero(), Choice::from(0));
+            }
+
+            let b = (0..(m - i - 1)).fold(z, |b, _| b.square());
+            m = i;
+            z = b.square();
+            c *= z;
+            r *= b;
+        }
+
+        CtOption::new(r, r.square().ct_eq(self))
+    }
+}
+
+impl PrimeField for Goldilocks {
+    type Repr = [u8; 8];
+
+    fn from_repr(repr: Self::Repr) -> CtOption<Self> {
+        let x = u64::from_le_bytes(repr);
+        let (_, borrow) = x.overflowing_sub(MODULUS);
+
+        CtOption::new(Self(x), Choice::from(borrow as u8))
+    }
+
+    fn to_repr(&self) -> Self::Repr {
+        self.0.to_le_bytes()
+    }
+
+    fn is_odd(&self) -> Choice {
+        Choice::from((self.0 & 1) as u8)
+    }
+
+    const NUM_BITS: u32 = 64;
+
+    const CAPACITY: u32 = 63;
+
+    fn multiplicative_generator() -> Self {
+        Self(7)
+    }
+
+    const S: u32 = 32;
+
+    // 7^(2^32 - 1)
+    fn root_of_unity() -> Self {
+        Self(1753635133440165772)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        let minus_one = Goldilocks(MODULUS - 1);
+        assert_eq!(-Goldilocks::one(), minus_one);
+        assert_eq!(minus_one + minus_one, Goldilocks(MODULUS - 2));
+        assert_eq!(Goldilocks::zero() - Goldilocks::one(), minus_one);
+        assert_eq!(minus_one * minus_one, Goldilocks::one());
+        assert_eq!(Goldilocks::from(u64::MAX), Goldilocks(EPSILON - 1));
+
+        // 2^96 = -1
+        let two_32 = Goldilocks::from(1 << 32);
+        assert_eq!(two_32 * two_32 * two_32, minus_one);
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let x = Goldilocks::random(&mut rng);
+            let y = Goldilocks::random(&mut rng);
+
+            // Against u128 arithmetic
+            let p = MODULUS as u128;
+            assert_eq!((x + y).0 as u128, (x.0 as u128 + y.0 as u128) % p);
+            assert_eq!((x - y).0 as u128, (x.0 as u128 + p - y.0 as u128) % p);
+            assert_eq!((x * y).0 as u128, (x.0 as u128 * y.0 as u128) % p);
+
+            assert_eq!(x * x.invert().unwrap(), Goldilocks::one());
+            assert_eq!(x.square().sqrt().unwrap().square(), x.square());
+        }
+
+        assert!(bool::from(Goldilocks::zero().invert().is_none()));
+        assert!(bool::from(
+            Goldilocks::multiplicative_generator().sqrt().is_none()
+        ));
+    }
+
+    #[test]
+    fn test_constants() {
+        let root = Goldilocks::root_of_unity();
+        assert_eq!(
+            root,
+            Goldilocks::multiplicative_generator().pow_vartime([EPSILON])
+        );
+        assert_eq!((0..32).fold(root, |x, _| x.square()), Goldilocks::one());
+        assert_eq!((0..31).fold(root, |x, _| x.square()), -Goldilocks::one());
+
+        assert!(bool::from(
+            Goldilocks::from_repr(MODULUS.to_le_bytes()).is_none()
+        ));
+        assert_eq!(
+            Goldilocks::from_str_vartime("18446744069414584320"),
+            Some(-Goldilocks::one())
+        );
+    }
+}
@@ -1,3 +1,10 @@
-pub(crate) mod poseidon;
+pub mod goldilocks;
+pub mod permutation;
+pub mod poseidon;
+pub mod poseidon2;
+#[cfg(test)]
+mod regression;
+pub mod rescue;
+pub mod spec;
 pub mod sponge;
 pub mod transcript;
@@ -0,0 +1,78 @@
+// The permutations that the sponge, and so the transcript, can be built on
+use crate::goldilocks::Goldilocks;
+use crate::poseidon::Poseidon;
+use crate::poseidon2::Poseidon2;
+use crate::rescue::{self, RescuePrime};
+use ff::PrimeField;
+
+/// A permutation of `[F; T]`, which permutes the state it holds in place.
+///
+/// The implementations of this crate take a time that doesn't depend on the
+/// state, which `benches/dudect.rs` checks, so that the sponge can absorb
+/// secrets. Other implementations must do the same for that to hold.
+pub trait Permutation<F: PrimeField, const T: usize> {
+    fn state(&self) -> &[F; T];
+
+    fn state_mut(&mut self) -> &mut [F; T];
+
+    fn permute(&mut self);
+}
+
+/// A permutation chosen at run time.
+pub type BoxedPermutation<F, const T: usize> = Box<dyn Permutation<F, T> + Send + Sync>;
+
+impl<F: PrimeField, const T: usize, P: Permutation<F, T> + ?Sized> Permutation<F, T> for Box<P> {
+    fn state(&self) -> &[F; T] {
+        (**self).state()
+    }
+
+    fn state_mut(&mut self) -> &mut [F; T] {
+        (**self).state_mut()
+    }
+
+    fn permute(&mut self) {
+        (**self).permute()
+    }
+}
+
+impl<F: PrimeField, const T: usize> Permutation<F, T> for Poseidon<F, T> {
+    fn state(&self) -> &[F; T] {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut [F; T] {
+        &mut self.state
+    }
+
+    fn permute(&mut self) {
+        Poseidon::permute(self)
+    }
+}
+
+impl<F: PrimeField, const T: usize> Permutation<F, T> for Poseidon2<F, T> {
+    fn state(&self) -> &[F; T] {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut [F; T] {
+        &mut self.state
+    }
+
+    fn permute(&mut self) {
+        Poseidon2::permute(self)
+    }
+}
+
+impl Permutation<Goldilocks, { rescue::WIDTH }> for RescuePrime {
+    fn state(&self) -> &[Goldilocks; rescue::WIDTH] {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut [Goldilocks; rescue::WIDTH] {
+        &mut self.state
+    }
+
+    fn permute(&mut self) {
+        RescuePrime::permute(self)
+    }
+}
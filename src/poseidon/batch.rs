@@ -0,0 +1,107 @@
+// Permutation of many independent states at a time. The states are permuted in
+// chunks, round after round, so that the constants and matrix of a round stay
+// in cache while they're applied to the whole chunk. With the `rayon` feature,
+// the chunks are permuted in parallel.
+use super::{full_round, pre_sparse_rounds, sparse_round, PoseidonConstants};
+use ff::PrimeField;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+// A chunk of states of the usual widths fits in the L1 cache
+const CHUNK_SIZE: usize = 32;
+
+/// Permute every state, with the same result as [`Poseidon::permute`] on each
+/// one of them.
+///
+/// [`Poseidon::permute`]: super::Poseidon::permute
+pub fn permute_batch<F: PrimeField, const T: usize>(
+    constants: &PoseidonConstants<F, T>,
+    states: &mut [[F; T]],
+) {
+    #[cfg(feature = "rayon")]
+    states
+        .par_chunks_mut(CHUNK_SIZE)
+        .for_each(|chunk| permute_chunk(constants, chunk));
+
+    #[cfg(not(feature = "rayon"))]
+    states
+        .chunks_mut(CHUNK_SIZE)
+        .for_each(|chunk| permute_chunk(constants, chunk));
+}
+
+fn permute_chunk<F: PrimeField, const T: usize>(
+    constants: &PoseidonConstants<F, T>,
+    states: &mut [[F; T]],
+) {
+    let full_rounds_half = constants.num_full_rounds / 2;
+
+    // First half of full rounds
+    for round in 0..full_rounds_half {
+        for state in states.iter_mut() {
+            full_round(constants, state, round * T);
+        }
+    }
+
+    // Partial rounds, with sparse matrices
+    if constants.num_partial_rounds > 0 {
+        for state in states.iter_mut() {
+            pre_sparse_rounds(constants, state);
+        }
+
+        for i in 0..constants.optimized.sparse_matrices.len() {
+            for state in states.iter_mut() {
+                sparse_round(constants, state, i);
+            }
+        }
+    }
+
+    // Second half of full rounds
+    let pos = (full_rounds_half + constants.num_partial_rounds) * T;
+    for round in 0..full_rounds_half {
+        for state in states.iter_mut() {
+            full_round(constants, state, pos + round * T);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon::Poseidon;
+    use crate::spec::{Bn254, PoseidonSpec, K256};
+    use halo2curves::bn256::Fr;
+    use halo2curves::secp256k1::Fp;
+
+    fn check_batch<F: PrimeField, const T: usize>(
+        constants: std::sync::Arc<PoseidonConstants<F, T>>,
+        num_states: usize,
+    ) {
+        let mut rng = rand::thread_rng();
+        let mut states: Vec<[F; T]> = (0..num_states)
+            .map(|_| [(); T].map(|_| F::random(&mut rng)))
+            .collect();
+
+        let mut poseidon = Poseidon::new(constants.clone(), [F::zero(); T]);
+        let expected: Vec<[F; T]> = states
+            .iter()
+            .map(|state| {
+                poseidon.state = *state;
+                poseidon.permute();
+                poseidon.state
+            })
+            .collect();
+
+        permute_batch(&constants, &mut states);
+        assert_eq!(states, expected);
+    }
+
+    #[test]
+    fn test_permute_batch() {
+        // Empty, partial and several chunks
+        check_batch::<Fp, 3>(K256::constants(), 0);
+        check_batch::<Fp, 3>(K256::constants(), 5);
+        check_batch::<Fp, 3>(K256::constants(), 3 * CHUNK_SIZE + 1);
+        check_batch::<Fp, 5>(K256::constants(), CHUNK_SIZE + 7);
+        check_batch::<Fr, 3>(Bn254::constants(), 2 * CHUNK_SIZE);
+    }
+}
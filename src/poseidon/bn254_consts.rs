@@ -0,0 +1,7 @@
+// The BN254 round numbers of circomlib's poseidon.circom.
+pub const NUM_FULL_ROUNDS: usize = 8;
+
+// Number of partial rounds for the widths 2 to 17
+pub const NUM_PARTIAL_ROUNDS_BY_WIDTH: [usize; 16] = [
+    56, 57, 56, 60, 60, 63, 64, 63, 60, 66, 60, 65, 70, 60, 64, 68,
+];
@@ -0,0 +1,247 @@
+// Round constant and MDS matrix generation as done by the reference scripts of
+// the Poseidon paper (https://extgit.iaik.tugraz.at/krypto/hadeshash).
+use super::{matrix, mds, rounds, PoseidonConstants};
+use ff::PrimeField;
+
+// The 80-bit self-shrinking Grain LFSR used to sample the constants.
+pub(crate) struct Grain {
+    state: u128,
+}
+
+impl Grain {
+    pub(crate) fn new(
+        field_size: u32,
+        width: usize,
+        num_full_rounds: usize,
+        num_partial_rounds: usize,
+    ) -> Self {
+        // The initial state encodes the parameters, most significant bit first:
+        // field type (2 bits), S-box type (4 bits), field size (12 bits),
+        // width (12 bits), R_F (10 bits), R_P (10 bits) and 30 bits set to one.
+        let fields = [
+            (1, 2), // prime field
+            (0, 4), // x^alpha S-box
+            (field_size as u128, 12),
+            (width as u128, 12),
+            (num_full_rounds as u128, 10),
+            (num_partial_rounds as u128, 10),
+            ((1 << 30) - 1, 30),
+        ];
+
+        let mut state = 0u128;
+        let mut pos = 0;
+        for (value, num_bits) in fields {
+            for i in (0..num_bits).rev() {
+                state |= ((value >> i) & 1) << pos;
+                pos += 1;
+            }
+        }
+
+        let mut grain = Self { state };
+
+        // Discard the first 160 bits
+        for _ in 0..160 {
+            grain.update();
+        }
+
+        grain
+    }
+
+    fn update(&mut self) -> bool {
+        let s = self.state;
+        let bit = ((s >> 62) ^ (s >> 51) ^ (s >> 38) ^ (s >> 23) ^ (s >> 13) ^ s) & 1;
+        self.state = (s >> 1) | (bit << 79);
+        bit == 1
+    }
+
+    // Output bits come in pairs: the second bit is kept only if the first one is set.
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let keep = self.update();
+            let bit = self.update();
+            if keep {
+                return bit;
+            }
+        }
+    }
+
+    fn next_bits(&mut self, num_bits: usize) -> Vec<bool> {
+        (0..num_bits).map(|_| self.next_bit()).collect()
+    }
+
+    // Sample a field element by rejection: draws of NUM_BITS bits that are not
+    // smaller than the modulus are skipped.
+    pub(crate) fn next_field_element<F: PrimeField>(&mut self) -> F {
+        loop {
+            let bits = self.next_bits(F::NUM_BITS as usize);
+            if let Some(fe) = field_element_from_bits(&bits) {
+                return fe;
+            }
+        }
+    }
+
+    // Sample a field element by reducing NUM_BITS bits modulo the field order.
+    pub(crate) fn next_field_element_without_rejection<F: PrimeField>(&mut self) -> F {
+        self.next_bits(F::NUM_BITS as usize)
+            .iter()
+            .fold(F::zero(), |acc, bit| {
+                if *bit {
+                    acc.double() + F::one()
+                } else {
+                    acc.double()
+                }
+            })
+    }
+}
+
+// Interpret big-endian bits as a canonical field element. `ff` leaves the byte
+// order of `PrimeField::Repr` to the implementation, so it's derived from the
+// encoding of one.
+fn field_element_from_bits<F: PrimeField>(bits: &[bool]) -> Option<F> {
+    let mut repr = F::Repr::default();
+    let little_endian = F::one().to_repr().as_ref()[0] == 1;
+
+    let bytes = repr.as_mut();
+    let num_bytes = bytes.len();
+    for (i, bit) in bits.iter().rev().enumerate() {
+        if *bit {
+            let byte = if little_endian {
+                i / 8
+            } else {
+                num_bytes - 1 - i / 8
+            };
+            bytes[byte] |= 1 << (i % 8);
+        }
+    }
+
+    F::from_repr(repr).into()
+}
+
+// Generate a Cauchy matrix M[i][j] = 1 / (x_i + y_j) from 2t distinct elements.
+fn generate_mds<F: PrimeField>(grain: &mut Grain, width: usize) -> Vec<Vec<F>> {
+    loop {
+        let elements: Vec<F> = (0..(2 * width))
+            .map(|_| grain.next_field_element_without_rejection())
+            .collect();
+
+        let distinct = elements
+            .iter()
+            .enumerate()
+            .all(|(i, x)| elements[(i + 1)..].iter().all(|y| x != y));
+        if !distinct {
+            continue;
+        }
+
+        let (xs, ys) = elements.split_at(width);
+        let mds_matrix: Option<Vec<Vec<F>>> = xs
+            .iter()
+            .map(|x| ys.iter().map(|y| Option::from((*x + y).invert())).collect())
+            .collect();
+
+        if let Some(mds_matrix) = mds_matrix {
+            return mds_matrix;
+        }
+    }
+}
+
+/// Generate the round constants and the MDS matrix of a Poseidon instance over
+/// `F` with the width `T` and the given S-box exponent and number of rounds, as
+/// the reference `generate_parameters_grain.sage` script does. Like the
+/// reference script, MDS matrices that fail the security checks are drawn again.
+pub fn generate_constants<F: PrimeField, const T: usize>(
+    alpha: u64,
+    num_full_rounds: usize,
+    num_partial_rounds: usize,
+) -> PoseidonConstants<F, T> {
+    let (round_constants, mds_matrix) =
+        generate_round_constants_and_mds(num_full_rounds, num_partial_rounds);
+
+    PoseidonConstants::new(
+        round_constants,
+        mds_matrix,
+        alpha,
+        num_full_rounds,
+        num_partial_rounds,
+    )
+}
+
+pub(crate) fn generate_round_constants_and_mds<F: PrimeField, const T: usize>(
+    num_full_rounds: usize,
+    num_partial_rounds: usize,
+) -> (Vec<F>, [[F; T]; T]) {
+    // Only x^alpha S-boxes are supported, which all share the same Grain seed.
+    let mut grain = Grain::new(F::NUM_BITS, T, num_full_rounds, num_partial_rounds);
+
+    let round_constants = (0..((num_full_rounds + num_partial_rounds) * T))
+        .map(|_| grain.next_field_element())
+        .collect();
+
+    let mds_matrix = loop {
+        let mds_matrix = matrix::to_array(&generate_mds(&mut grain, T));
+        if mds::validate_mds_matrix(&mds_matrix).is_ok() {
+            break mds_matrix;
+        }
+    };
+
+    (round_constants, mds_matrix)
+}
+
+/// Generate the constants of a Poseidon instance over `F` with the width `T` and
+/// the given S-box exponent, with the round numbers given by [`round_numbers`]
+/// for the security level in bits.
+///
+/// [`round_numbers`]: super::round_numbers
+pub fn generate_secure_constants<F: PrimeField, const T: usize>(
+    alpha: u64,
+    security_level: u32,
+) -> PoseidonConstants<F, T> {
+    let (num_full_rounds, num_partial_rounds) =
+        rounds::round_numbers(rounds::log2_modulus::<F>(), T, alpha, security_level);
+
+    generate_constants(alpha, num_full_rounds, num_partial_rounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon::k256_consts;
+    use halo2curves::secp256k1::Fp;
+
+    #[test]
+    fn test_k256_constants() {
+        let constants = generate_constants::<Fp, 3>(
+            5,
+            k256_consts::NUM_FULL_ROUNDS,
+            k256_consts::NUM_PARTIAL_ROUNDS,
+        );
+
+        let round_constants: Vec<[u8; 32]> = k256_consts::ROUND_CONSTANTS
+            .iter()
+            .map(|x| Fp::from_str_vartime(x).unwrap().to_repr())
+            .collect();
+
+        let mds_matrix: Vec<[u8; 32]> = k256_consts::MDS_MATRIX
+            .iter()
+            .flatten()
+            .map(|x| Fp::from_str_vartime(x).unwrap().to_repr())
+            .collect();
+
+        assert_eq!(
+            constants
+                .round_keys
+                .iter()
+                .map(|x| x.to_repr())
+                .collect::<Vec<[u8; 32]>>(),
+            round_constants
+        );
+        assert_eq!(
+            constants
+                .mds_matrix
+                .iter()
+                .flatten()
+                .map(|x| x.to_repr())
+                .collect::<Vec<[u8; 32]>>(),
+            mds_matrix
+        );
+    }
+}
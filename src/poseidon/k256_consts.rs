@@ -1,7 +1,8 @@
+// The K256 width 3 constants, generated by the hadeshash Grain LFSR.
 pub const NUM_FULL_ROUNDS: usize = 8;
 pub const NUM_PARTIAL_ROUNDS: usize = 56;
 
-pub static MDS_MATRIX: &'static [[&str; 3]; 3] = &[
+pub static MDS_MATRIX: &[[&str; 3]; 3] = &[
     [
         "92469348809186613947252340883344274339611751744959319352506666082431267346705",
         "100938028378191533449096235266991198229563815869344032449592738345766724371160",
@@ -19,7 +20,7 @@ pub static MDS_MATRIX: &'static [[&str; 3]; 3] = &[
     ],
 ];
 
-pub static ROUND_CONSTANTS: &'static [&str; 192] = &[
+pub static ROUND_CONSTANTS: &[&str; 192] = &[
     "15180568604901803243989155929934437997245952775071395385994322939386074967328",
     "98155933184944822056372510812105826951789406432246960633912199752807271851218",
     "32585497418154084368870158853355239726261349829448673320273043226636389078017",
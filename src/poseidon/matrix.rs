@@ -0,0 +1,211 @@
+// Dense matrix helpers over a prime field. Matrices are stored as rows.
+use ff::PrimeField;
+
+pub(crate) fn identity<F: PrimeField>(n: usize) -> Vec<Vec<F>> {
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| if i == j { F::one() } else { F::zero() })
+                .collect()
+        })
+        .collect()
+}
+
+pub(crate) fn transpose<F: PrimeField>(m: &[Vec<F>]) -> Vec<Vec<F>> {
+    (0..m[0].len())
+        .map(|j| m.iter().map(|row| row[j]).collect())
+        .collect()
+}
+
+pub(crate) fn mul<F: PrimeField>(a: &[Vec<F>], b: &[Vec<F>]) -> Vec<Vec<F>> {
+    a.iter()
+        .map(|row| {
+            (0..b[0].len())
+                .map(|j| {
+                    row.iter()
+                        .zip(b.iter())
+                        .fold(F::zero(), |acc, (x, b_row)| acc + *x * b_row[j])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub(crate) fn mul_vec<F: PrimeField>(m: &[Vec<F>], v: &[F]) -> Vec<F> {
+    m.iter()
+        .map(|row| {
+            row.iter()
+                .zip(v.iter())
+                .fold(F::zero(), |acc, (x, y)| acc + *x * y)
+        })
+        .collect()
+}
+
+pub(crate) fn mul_array<F: PrimeField, const T: usize>(m: &[[F; T]; T], v: &[F; T]) -> [F; T] {
+    std::array::from_fn(|i| {
+        m[i].iter()
+            .zip(v.iter())
+            .fold(F::zero(), |acc, (x, y)| acc + *x * y)
+    })
+}
+
+pub(crate) fn to_array<F: PrimeField, const T: usize>(m: &[Vec<F>]) -> [[F; T]; T] {
+    std::array::from_fn(|i| std::array::from_fn(|j| m[i][j]))
+}
+
+// Gauss-Jordan elimination. Returns None if the matrix is singular.
+pub(crate) fn invert<F: PrimeField>(m: &[Vec<F>]) -> Option<Vec<Vec<F>>> {
+    let n = m.len();
+    let mut a = m.to_vec();
+    let mut inv = identity(n);
+
+    for col in 0..n {
+        let pivot = (col..n).find(|&row| !bool::from(a[row][col].is_zero()))?;
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+
+        let pivot_inv = a[col][col].invert().unwrap();
+        for j in 0..n {
+            a[col][j] *= pivot_inv;
+            inv[col][j] *= pivot_inv;
+        }
+
+        for row in 0..n {
+            if row == col {
+                continue;
+            }
+
+            let factor = a[row][col];
+            if bool::from(factor.is_zero()) {
+                continue;
+            }
+
+            for j in 0..n {
+                let a_col_j = a[col][j];
+                let inv_col_j = inv[col][j];
+                a[row][j] -= factor * a_col_j;
+                inv[row][j] -= factor * inv_col_j;
+            }
+        }
+    }
+
+    Some(inv)
+}
+
+// Reduced row echelon form by Gauss-Jordan elimination. Returns the pivot
+// column of each nonzero row, which come first.
+fn row_reduce<F: PrimeField>(m: &mut [Vec<F>]) -> Vec<usize> {
+    let num_cols = m.first().map_or(0, |row| row.len());
+    let mut pivots = vec![];
+
+    for col in 0..num_cols {
+        let row = pivots.len();
+        let pivot = match (row..m.len()).find(|&i| !bool::from(m[i][col].is_zero())) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        m.swap(row, pivot);
+
+        let pivot_inv = m[row][col].invert().unwrap();
+        for x in m[row].iter_mut() {
+            *x *= pivot_inv;
+        }
+
+        for i in 0..m.len() {
+            let factor = m[i][col];
+            if i == row || bool::from(factor.is_zero()) {
+                continue;
+            }
+
+            let pivot_row = m[row].clone();
+            for (x, y) in m[i].iter_mut().zip(pivot_row.iter()) {
+                *x -= factor * y;
+            }
+        }
+
+        pivots.push(col);
+    }
+
+    pivots
+}
+
+// Rank of the space spanned by the rows
+pub(crate) fn rank<F: PrimeField>(m: &[Vec<F>]) -> usize {
+    row_reduce(&mut m.to_vec()).len()
+}
+
+// Basis of the right kernel {x : m * x = 0}, for n columns
+pub(crate) fn kernel<F: PrimeField>(m: &[Vec<F>], n: usize) -> Vec<Vec<F>> {
+    let mut m = m.to_vec();
+    let pivots = row_reduce(&mut m);
+
+    (0..n)
+        .filter(|col| !pivots.contains(col))
+        .map(|free| {
+            let mut x = vec![F::zero(); n];
+            x[free] = F::one();
+            for (row, pivot) in pivots.iter().enumerate() {
+                x[*pivot] = -m[row][free];
+            }
+            x
+        })
+        .collect()
+}
+
+// Coordinates of v in the basis of linearly independent vectors, if v is in
+// their span.
+pub(crate) fn coordinates<F: PrimeField>(basis: &[Vec<F>], v: &[F]) -> Option<Vec<F>> {
+    // Columns are the basis vectors followed by v
+    let mut m: Vec<Vec<F>> = (0..v.len())
+        .map(|i| basis.iter().map(|b| b[i]).chain([v[i]]).collect())
+        .collect();
+    let pivots = row_reduce(&mut m);
+
+    if pivots.contains(&basis.len()) {
+        return None;
+    }
+
+    Some((0..basis.len()).map(|row| m[row][basis.len()]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use halo2curves::secp256k1::Fp;
+
+    #[test]
+    fn test_invert() {
+        let m: Vec<Vec<Fp>> = [[0, 2, 1], [1, 1, 0], [3, 0, 5]]
+            .iter()
+            .map(|row| row.iter().map(|x| Fp::from(*x)).collect())
+            .collect();
+
+        let inv = invert(&m).unwrap();
+        assert_eq!(mul(&m, &inv), identity(3));
+
+        let singular: Vec<Vec<Fp>> = vec![vec![Fp::one(), Fp::from(2)]; 2];
+        assert!(invert(&singular).is_none());
+    }
+
+    #[test]
+    fn test_kernel() {
+        let m: Vec<Vec<Fp>> = [[1, 2, 3], [2, 4, 6]]
+            .iter()
+            .map(|row| row.iter().map(|x| Fp::from(*x)).collect())
+            .collect();
+
+        assert_eq!(rank(&m), 1);
+
+        let kernel = kernel(&m, 3);
+        assert_eq!(kernel.len(), 2);
+        for x in kernel.iter() {
+            assert!(mul_vec(&m, x).iter().all(|y| bool::from(y.is_zero())));
+        }
+
+        let v = vec![Fp::one(); 3];
+        let c = coordinates(&kernel, &kernel[0]).unwrap();
+        assert_eq!(c, vec![Fp::one(), Fp::zero()]);
+        assert!(coordinates(&kernel, &v).is_none());
+    }
+}
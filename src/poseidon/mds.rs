@@ -0,0 +1,435 @@
+// Security checks of the MDS matrix as done by the reference scripts of the
+// Poseidon paper. Every square submatrix must be invertible, and the linear
+// layer must not give infinitely long subspace trails through the partial
+// rounds, following Algorithms 1 to 3 of Grassi, Rechberger and Schofnegger,
+// "Proving Resistance Against Infinitely Long Subspace Trails: How to Choose the
+// Linear Layer" (https://eprint.iacr.org/2020/500), with one S-box per partial
+// round on the first element.
+use super::{matrix, modulus_minus_one_be};
+use ff::PrimeField;
+use std::fmt;
+
+// The widest matrix whose square submatrices are all checked, when it isn't a
+// Cauchy matrix. There are (2t choose t) of them, 12870 for the width 8.
+pub const MAX_BRUTE_FORCE_WIDTH: usize = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MdsError {
+    NotMds { rows: Vec<usize>, cols: Vec<usize> },
+    ScalarPower { power: usize },
+    InvariantSubspace { rounds: usize },
+    InvariantInactiveSubspace { rounds: usize, power: usize },
+    SubspaceTrail { power: usize },
+    // A matrix wider than MAX_BRUTE_FORCE_WIDTH that isn't a Cauchy matrix
+    UncheckedWidth { width: usize, max: usize },
+}
+
+impl fmt::Display for MdsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MdsError::NotMds { rows, cols } => write!(
+                f,
+                "the submatrix with the rows {:?} and the columns {:?} is singular, so the matrix is not MDS",
+                rows, cols
+            ),
+            MdsError::ScalarPower { power } => {
+                write!(f, "M^{} is a multiple of the identity", power)
+            }
+            MdsError::InvariantSubspace { rounds } => write!(
+                f,
+                "M^{} has an eigenvector that keeps the S-box inactive for {} partial rounds, \
+                 which gives an infinitely long invariant subspace trail",
+                rounds, rounds
+            ),
+            MdsError::InvariantInactiveSubspace { rounds, power } => write!(
+                f,
+                "the subspace that keeps the S-box inactive for {} partial rounds is invariant under M^{}",
+                rounds, power
+            ),
+            MdsError::SubspaceTrail { power } => write!(
+                f,
+                "the first unit vector doesn't span the whole space under M^{}, \
+                 which gives an infinitely long subspace trail through the partial rounds",
+                power
+            ),
+            MdsError::UncheckedWidth { width, max } => write!(
+                f,
+                "only Cauchy matrices are checked beyond the width {}, and the {}x{} matrix is not one",
+                max, width, width
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MdsError {}
+
+/// Check that the matrix is MDS and doesn't admit the infinitely long subspace
+/// trails through the partial rounds that the reference scripts reject.
+pub fn validate_mds_matrix<F: PrimeField, const T: usize>(
+    mds_matrix: &[[F; T]; T],
+) -> Result<(), MdsError> {
+    let m: Vec<Vec<F>> = mds_matrix.iter().map(|row| row.to_vec()).collect();
+
+    check_mds(&m)?;
+    validate_partial_round_matrix(&m)
+}
+
+// The subspace trail checks alone, for the matrices of the partial rounds that
+// don't have to be MDS, like the internal matrix of Poseidon2.
+pub(crate) fn validate_partial_round_matrix<F: PrimeField>(m: &[Vec<F>]) -> Result<(), MdsError> {
+    check_invariant_subspaces(m)?;
+    check_subspace_trails(m)
+}
+
+fn check_mds<F: PrimeField>(m: &[Vec<F>]) -> Result<(), MdsError> {
+    let t = m.len();
+
+    for (i, row) in m.iter().enumerate() {
+        if let Some(j) = row.iter().position(|x| bool::from(x.is_zero())) {
+            return Err(MdsError::NotMds {
+                rows: vec![i],
+                cols: vec![j],
+            });
+        }
+    }
+
+    if let Some(result) = check_cauchy(m) {
+        return result;
+    }
+
+    // Otherwise check every square submatrix, which is exponential in the width
+    if t > MAX_BRUTE_FORCE_WIDTH {
+        return Err(MdsError::UncheckedWidth {
+            width: t,
+            max: MAX_BRUTE_FORCE_WIDTH,
+        });
+    }
+
+    let subsets: Vec<Vec<usize>> = (1..(1u32 << t))
+        .map(|mask| (0..t).filter(|i| (mask >> i) & 1 == 1).collect())
+        .collect();
+
+    for rows in subsets.iter().filter(|rows| rows.len() >= 2) {
+        for cols in subsets.iter().filter(|cols| cols.len() == rows.len()) {
+            let submatrix: Vec<Vec<F>> = rows
+                .iter()
+                .map(|i| cols.iter().map(|j| m[*i][*j]).collect())
+                .collect();
+
+            if matrix::invert(&submatrix).is_none() {
+                return Err(MdsError::NotMds {
+                    rows: rows.clone(),
+                    cols: cols.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// A Cauchy matrix M[i][j] = 1 / (x_i + y_j) is MDS if and only if the x_i are
+// distinct and the y_j are distinct. Returns None if the matrix is not a Cauchy
+// matrix. The entries must be nonzero.
+fn check_cauchy<F: PrimeField>(m: &[Vec<F>]) -> Option<Result<(), MdsError>> {
+    let t = m.len();
+    let inv: Vec<Vec<F>> = m
+        .iter()
+        .map(|row| row.iter().map(|x| x.invert().unwrap()).collect())
+        .collect();
+
+    // With y_0 = 0, x_i = inv[i][0] and y_j = inv[0][j] - inv[0][0]
+    for i in 0..t {
+        for j in 0..t {
+            if inv[i][j] != inv[i][0] + inv[0][j] - inv[0][0] {
+                return None;
+            }
+        }
+    }
+
+    for i in 0..t {
+        for k in (i + 1)..t {
+            if inv[i][0] == inv[k][0] {
+                return Some(Err(MdsError::NotMds {
+                    rows: vec![i, k],
+                    cols: vec![0, 1],
+                }));
+            }
+            if inv[0][i] == inv[0][k] {
+                return Some(Err(MdsError::NotMds {
+                    rows: vec![0, 1],
+                    cols: vec![i, k],
+                }));
+            }
+        }
+    }
+
+    Some(Ok(()))
+}
+
+// Algorithm 1: for r < t rounds, M^r must not be a multiple of the identity, and
+// the subspace of states that keep the S-box inactive for r rounds must neither
+// contain an eigenvector of M^r nor be invariant under M^j for j <= r.
+fn check_invariant_subspaces<F: PrimeField>(m: &[Vec<F>]) -> Result<(), MdsError> {
+    let t = m.len();
+    let mut powers = vec![matrix::identity(t)];
+    for i in 1..t {
+        powers.push(matrix::mul(&powers[i - 1], m));
+    }
+
+    for rounds in 1..t {
+        let m_r = &powers[rounds];
+
+        let scalar: Vec<Vec<F>> = matrix::identity::<F>(t)
+            .iter()
+            .map(|row| row.iter().map(|x| *x * m_r[0][0]).collect())
+            .collect();
+        if *m_r == scalar {
+            return Err(MdsError::ScalarPower { power: rounds });
+        }
+
+        // x_0 = 0 and (M^k x)_0 = 0 for 0 < k < rounds
+        let constraints: Vec<Vec<F>> = (0..rounds).map(|k| powers[k][0].clone()).collect();
+        let inactive = matrix::kernel(&constraints, t);
+
+        // Eigenvectors of M^r in the inactive subspace are in its largest
+        // M^r-invariant subspace W. They exist if the characteristic
+        // polynomial of M^r restricted to W has a root in F.
+        let m_r_transpose = matrix::transpose(m_r);
+        let mut invariant_constraints = constraints.clone();
+        for k in 1..t {
+            let previous = &invariant_constraints[(k - 1) * rounds..k * rounds];
+            let next: Vec<Vec<F>> = previous
+                .iter()
+                .map(|c| matrix::mul_vec(&m_r_transpose, c))
+                .collect();
+            invariant_constraints.extend(next);
+        }
+
+        let invariant = matrix::kernel(&invariant_constraints, t);
+        if !invariant.is_empty() {
+            let restricted = matrix::transpose(
+                &invariant
+                    .iter()
+                    .map(|w| matrix::coordinates(&invariant, &matrix::mul_vec(m_r, w)).unwrap())
+                    .collect::<Vec<Vec<F>>>(),
+            );
+
+            if has_root(&characteristic_polynomial(&restricted)) {
+                return Err(MdsError::InvariantSubspace { rounds });
+            }
+        }
+
+        for (power, m_j) in powers.iter().enumerate().take(rounds + 1).skip(1) {
+            let is_invariant = inactive.iter().all(|v| {
+                let image = matrix::mul_vec(m_j, v);
+                matrix::mul_vec(&constraints, &image)
+                    .iter()
+                    .all(|x| bool::from(x.is_zero()))
+            });
+
+            if is_invariant {
+                return Err(MdsError::InvariantInactiveSubspace { rounds, power });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Algorithms 2 and 3: for r <= 4t, the first unit vector must span the whole
+// space under M^r.
+fn check_subspace_trails<F: PrimeField>(m: &[Vec<F>]) -> Result<(), MdsError> {
+    let t = m.len();
+    let mut m_r = matrix::identity(t);
+
+    for power in 1..=(4 * t) {
+        m_r = matrix::mul(&m_r, m);
+
+        let mut krylov = vec![matrix::identity::<F>(t).swap_remove(0)];
+        for k in 1..t {
+            let next = matrix::mul_vec(&m_r, &krylov[k - 1]);
+            krylov.push(next);
+        }
+
+        if matrix::rank(&krylov) < t {
+            return Err(MdsError::SubspaceTrail { power });
+        }
+    }
+
+    Ok(())
+}
+
+// Polynomials over F are stored as coefficients from the lowest degree, without
+// trailing zeros.
+
+fn trim<F: PrimeField>(mut a: Vec<F>) -> Vec<F> {
+    while a.last().map_or(false, |x| bool::from(x.is_zero())) {
+        a.pop();
+    }
+    a
+}
+
+fn poly_rem<F: PrimeField>(a: Vec<F>, f: &[F]) -> Vec<F> {
+    let mut r = trim(a);
+    let lead_inv = f.last().unwrap().invert().unwrap();
+
+    while r.len() >= f.len() {
+        let factor = *r.last().unwrap() * lead_inv;
+        let shift = r.len() - f.len();
+        for (i, c) in f.iter().enumerate() {
+            r[shift + i] -= factor * c;
+        }
+        r.pop();
+        r = trim(r);
+    }
+
+    r
+}
+
+fn poly_mul<F: PrimeField>(a: &[F], b: &[F]) -> Vec<F> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+
+    let mut c = vec![F::zero(); a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            c[i + j] += *x * y;
+        }
+    }
+    c
+}
+
+fn poly_gcd<F: PrimeField>(a: Vec<F>, b: Vec<F>) -> Vec<F> {
+    let (mut a, mut b) = (trim(a), trim(b));
+    while !b.is_empty() {
+        let r = poly_rem(a, &b);
+        a = b;
+        b = r;
+    }
+    a
+}
+
+// A polynomial f has a root in F if and only if gcd(f, x^p - x) isn't constant.
+fn has_root<F: PrimeField>(f: &[F]) -> bool {
+    if f.len() < 2 {
+        return false;
+    }
+
+    // p is odd, so its bytes are those of p - 1 with the lowest bit set
+    let mut modulus = modulus_minus_one_be::<F>();
+    *modulus.last_mut().unwrap() |= 1;
+
+    // x^p mod f
+    let x = vec![F::zero(), F::one()];
+    let mut x_p = vec![F::one()];
+    for byte in modulus {
+        for i in (0..8).rev() {
+            x_p = poly_rem(poly_mul(&x_p, &x_p), f);
+            if (byte >> i) & 1 == 1 {
+                x_p = poly_rem(poly_mul(&x_p, &x), f);
+            }
+        }
+    }
+
+    x_p.resize(x_p.len().max(2), F::zero());
+    x_p[1] -= F::one();
+
+    poly_gcd(f.to_vec(), x_p).len() >= 2
+}
+
+// Characteristic polynomial by the Faddeev-LeVerrier algorithm
+fn characteristic_polynomial<F: PrimeField>(a: &[Vec<F>]) -> Vec<F> {
+    let n = a.len();
+    let mut coeffs = vec![F::zero(); n + 1];
+    coeffs[n] = F::one();
+
+    let mut m = vec![vec![F::zero(); n]; n];
+    for k in 1..=n {
+        m = matrix::mul(a, &m);
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] += coeffs[n - k + 1];
+        }
+
+        let am = matrix::mul(a, &m);
+        let trace = (0..n).fold(F::zero(), |acc, i| acc + am[i][i]);
+        coeffs[n - k] = -trace * F::from(k as u64).invert().unwrap();
+    }
+
+    coeffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon::k256_consts;
+    use halo2curves::secp256k1::Fp;
+
+    fn to_matrix<const T: usize>(m: [[u64; T]; T]) -> [[Fp; T]; T] {
+        m.map(|row| row.map(Fp::from))
+    }
+
+    #[test]
+    fn test_k256() {
+        let mds_matrix =
+            k256_consts::MDS_MATRIX.map(|row| row.map(|x| Fp::from_str_vartime(x).unwrap()));
+
+        assert_eq!(validate_mds_matrix(&mds_matrix), Ok(()));
+    }
+
+    #[test]
+    fn test_rejected_matrices() {
+        // The 2x2 submatrix of the first two rows and columns is singular
+        assert_eq!(
+            validate_mds_matrix(&to_matrix([[1, 2, 3], [2, 4, 5], [3, 5, 7]])),
+            Err(MdsError::NotMds {
+                rows: vec![0, 1],
+                cols: vec![0, 1]
+            })
+        );
+
+        // [[1, 1], [1, -1]] squares to twice the identity, under which the first
+        // unit vector only spans itself
+        assert_eq!(
+            validate_mds_matrix(&[[Fp::one(), Fp::one()], [Fp::one(), -Fp::one()]]),
+            Err(MdsError::SubspaceTrail { power: 2 })
+        );
+
+        // circ(2, 1, 1) fixes the vectors whose elements sum to zero, such as
+        // (0, 1, -1) which keeps the S-box inactive
+        assert_eq!(
+            validate_mds_matrix(&to_matrix([[2, 1, 1], [1, 2, 1], [1, 1, 2]])),
+            Err(MdsError::InvariantSubspace { rounds: 1 })
+        );
+    }
+
+    #[test]
+    fn test_wide_matrices() {
+        // 1 / (x_i + y_j) with x_i = i and y_j = 9 + j is checked in polynomial time
+        let cauchy: [[Fp; 9]; 9] = std::array::from_fn(|i| {
+            std::array::from_fn(|j| ff::Field::invert(&Fp::from((i + 9 + j) as u64)).unwrap())
+        });
+        assert_eq!(check_mds(&cauchy.map(|row| row.to_vec())), Ok(()));
+
+        // The all-ones matrix plus the identity is not a Cauchy matrix
+        let mut m = [[1; 9]; 9];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 2;
+        }
+        assert_eq!(
+            validate_mds_matrix(&to_matrix(m)),
+            Err(MdsError::UncheckedWidth {
+                width: 9,
+                max: MAX_BRUTE_FORCE_WIDTH
+            })
+        );
+    }
+
+    #[test]
+    fn test_has_root() {
+        // x^2 - 4 and x^2 + 1, where -1 is not a square modulo the secp256k1 prime
+        assert!(has_root(&[-Fp::from(4), Fp::zero(), Fp::one()]));
+        assert!(!has_root(&[Fp::one(), Fp::zero(), Fp::one()]));
+    }
+}
@@ -0,0 +1,113 @@
+// Equivalent representation of the partial rounds from Appendix B of the Poseidon
+// paper (https://eprint.iacr.org/2019/458), as used by neptune and circomlib.
+//
+// Round constants of the partial rounds are moved backwards through the linear
+// layer, so each partial round only adds a single constant after its S-box. The MDS
+// matrix M of each partial round is then factored as M = S * P, where
+// P = diag(1, M_hat) commutes with the S-box of that round and is merged into the
+// previous round's matrix, leaving a sparse matrix S in every partial round.
+use super::matrix;
+use ff::PrimeField;
+
+// Sparse matrix [[m_00, row], [col, I]]
+pub struct SparseMatrix<F: PrimeField> {
+    pub m_00: F,
+    pub row: Vec<F>,
+    pub col: Vec<F>,
+}
+
+impl<F: PrimeField> SparseMatrix<F> {
+    pub(crate) fn mul(&self, state: &mut [F]) {
+        let s_0 = state[0];
+
+        let mut first = self.m_00 * s_0;
+        for (x, v) in state[1..].iter().zip(self.row.iter()) {
+            first += *x * v;
+        }
+
+        for (x, w) in state[1..].iter_mut().zip(self.col.iter()) {
+            *x += *w * s_0;
+        }
+
+        state[0] = first;
+    }
+}
+
+pub struct OptimizedConstants<F: PrimeField, const T: usize> {
+    // Added to the whole state before the first partial round
+    pub first_partial_round_keys: [F; T],
+    // Added to the first element after the S-box of all but the last partial round
+    pub partial_round_keys: Vec<F>,
+    // Applied once, before the first partial round
+    pub pre_sparse_matrix: [[F; T]; T],
+    pub sparse_matrices: Vec<SparseMatrix<F>>,
+}
+
+impl<F: PrimeField, const T: usize> OptimizedConstants<F, T> {
+    pub fn new(
+        round_keys: &[F],
+        mds_matrix: &[[F; T]; T],
+        num_full_rounds: usize,
+        num_partial_rounds: usize,
+    ) -> Self {
+        let t = T;
+        let full_rounds_half = num_full_rounds / 2;
+
+        let mds_matrix: Vec<Vec<F>> = mds_matrix.iter().map(|row| row.to_vec()).collect();
+        let mds_inv = matrix::invert(&mds_matrix).expect("the MDS matrix must be invertible");
+
+        // Move the constants of each partial round but the first one backwards
+        // through the MDS matrix of the previous round.
+        let mut round_keys: Vec<Vec<F>> = round_keys.chunks(t).map(|c| c.to_vec()).collect();
+        let mut partial_round_keys = vec![F::zero(); num_partial_rounds.saturating_sub(1)];
+        for i in ((full_rounds_half + 1)..(full_rounds_half + num_partial_rounds)).rev() {
+            let moved = matrix::mul_vec(&mds_inv, &round_keys[i]);
+
+            for j in 1..t {
+                round_keys[i - 1][j] += moved[j];
+            }
+            partial_round_keys[i - 1 - full_rounds_half] = moved[0];
+        }
+
+        // Factor the effective matrix of each partial round, starting from the last one.
+        let mut sparse_matrices = Vec::with_capacity(num_partial_rounds);
+        let mut pre_sparse_matrix = matrix::identity(t);
+        let mut m = mds_matrix.to_vec();
+        for _ in 0..num_partial_rounds {
+            let m_hat: Vec<Vec<F>> = m[1..].iter().map(|row| row[1..].to_vec()).collect();
+            let m_hat_inv =
+                matrix::invert(&m_hat).expect("the MDS matrix minors must be invertible");
+
+            // row = M_hat^-T * v
+            let row = matrix::mul_vec(&matrix::transpose(&m_hat_inv), &m[0][1..]);
+            let col = m[1..].iter().map(|row| row[0]).collect();
+
+            sparse_matrices.push(SparseMatrix {
+                m_00: m[0][0],
+                row,
+                col,
+            });
+
+            pre_sparse_matrix = matrix::identity(t);
+            for (i, m_hat_row) in m_hat.into_iter().enumerate() {
+                pre_sparse_matrix[i + 1][1..].copy_from_slice(&m_hat_row);
+            }
+
+            m = matrix::mul(&pre_sparse_matrix, &mds_matrix);
+        }
+        sparse_matrices.reverse();
+
+        let first_partial_round_keys = if num_partial_rounds > 0 {
+            std::array::from_fn(|j| round_keys[full_rounds_half][j])
+        } else {
+            [F::zero(); T]
+        };
+
+        Self {
+            first_partial_round_keys,
+            partial_round_keys,
+            pre_sparse_matrix: matrix::to_array(&pre_sparse_matrix),
+            sparse_matrices,
+        }
+    }
+}
@@ -0,0 +1,379 @@
+// Parameter files holding the constants of a Poseidon instance, so that they can
+// be generated and reviewed separately from the code. Field elements, and the
+// modulus, are 0x-prefixed big-endian hex strings of the length of the field
+// encoding, and the round constants are listed round after round. The hash mode
+// is part of the file, as the outputs of `Poseidon::hash` depend on it.
+use super::{
+    from_bytes_be, modulus_minus_one_be, to_bytes_be, HashMode, MdsError, PoseidonConstants,
+};
+use ff::PrimeField;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The version of the parameter file format written by this crate, and the only
+/// one it loads.
+pub const PARAMS_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParamsFile {
+    version: u32,
+    modulus: String,
+    width: usize,
+    alpha: u64,
+    num_full_rounds: usize,
+    num_partial_rounds: usize,
+    hash_mode: HashMode,
+    round_constants: Vec<String>,
+    mds_matrix: Vec<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParamsError {
+    Syntax(String),
+    UnsupportedVersion(u32),
+    ModulusMismatch { expected: String, found: String },
+    WidthMismatch { expected: usize, found: usize },
+    InvalidAlpha(u64),
+    OddFullRounds(usize),
+    RoundConstantCount { expected: usize, found: usize },
+    MdsMatrixShape { width: usize },
+    NonCanonical(String),
+    Mds(MdsError),
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamsError::Syntax(err) => write!(f, "malformed parameter file: {}", err),
+            ParamsError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported parameter file version {}, expected {}",
+                version, PARAMS_VERSION
+            ),
+            ParamsError::ModulusMismatch { expected, found } => write!(
+                f,
+                "the modulus {} is not the modulus {} of the field",
+                found, expected
+            ),
+            ParamsError::WidthMismatch { expected, found } => {
+                write!(
+                    f,
+                    "the width {} is not the expected width {}",
+                    found, expected
+                )
+            }
+            ParamsError::InvalidAlpha(alpha) => {
+                write!(f, "x^{} is not a permutation of the field", alpha)
+            }
+            ParamsError::OddFullRounds(num_full_rounds) => write!(
+                f,
+                "the number of full rounds {} must be even",
+                num_full_rounds
+            ),
+            ParamsError::RoundConstantCount { expected, found } => write!(
+                f,
+                "there are {} round constants instead of (R_F + R_P) * t = {}",
+                found, expected
+            ),
+            ParamsError::MdsMatrixShape { width } => {
+                write!(f, "the MDS matrix must be {} by {}", width, width)
+            }
+            ParamsError::NonCanonical(element) => {
+                write!(f, "{} is not a canonical field element", element)
+            }
+            ParamsError::Mds(err) => write!(f, "invalid MDS matrix: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
+impl From<MdsError> for ParamsError {
+    fn from(err: MdsError) -> Self {
+        ParamsError::Mds(err)
+    }
+}
+
+impl<F: PrimeField, const T: usize> PoseidonConstants<F, T> {
+    /// Load the constants from a JSON parameter file.
+    pub fn from_json(json: &str) -> Result<Self, ParamsError> {
+        let file =
+            serde_json::from_str(json).map_err(|err| ParamsError::Syntax(err.to_string()))?;
+        Self::from_params_file(file)
+    }
+
+    /// Load the constants from a TOML parameter file.
+    pub fn from_toml(toml: &str) -> Result<Self, ParamsError> {
+        let file = toml::from_str(toml).map_err(|err| ParamsError::Syntax(err.to_string()))?;
+        Self::from_params_file(file)
+    }
+
+    /// Export the constants to a JSON parameter file.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_params_file()).unwrap()
+    }
+
+    /// Export the constants to a TOML parameter file.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(&self.to_params_file()).unwrap()
+    }
+
+    fn from_params_file(file: ParamsFile) -> Result<Self, ParamsError> {
+        if file.version != PARAMS_VERSION {
+            return Err(ParamsError::UnsupportedVersion(file.version));
+        }
+
+        let expected = encode_bytes(&modulus_be::<F>());
+        if parse_hex(&file.modulus).map(|bytes| trim_leading_zeros(&bytes).to_vec())
+            != Some(trim_leading_zeros(&modulus_be::<F>()).to_vec())
+        {
+            return Err(ParamsError::ModulusMismatch {
+                expected,
+                found: file.modulus,
+            });
+        }
+
+        if file.width != T {
+            return Err(ParamsError::WidthMismatch {
+                expected: T,
+                found: file.width,
+            });
+        }
+
+        if file.mds_matrix.len() != T || file.mds_matrix.iter().any(|row| row.len() != T) {
+            return Err(ParamsError::MdsMatrixShape { width: T });
+        }
+
+        let round_constants = file
+            .round_constants
+            .iter()
+            .map(|x| decode(x))
+            .collect::<Result<Vec<F>, _>>()?;
+
+        let mut mds_matrix = [[F::zero(); T]; T];
+        for (row, elements) in mds_matrix.iter_mut().zip(file.mds_matrix.iter()) {
+            for (x, element) in row.iter_mut().zip(elements.iter()) {
+                *x = decode(element)?;
+            }
+        }
+
+        // The S-box, the number of rounds and the MDS matrix are checked there
+        Ok(Self::try_new(
+            round_constants,
+            mds_matrix,
+            file.alpha,
+            file.num_full_rounds,
+            file.num_partial_rounds,
+        )?
+        .with_hash_mode(file.hash_mode))
+    }
+
+    fn to_params_file(&self) -> ParamsFile {
+        ParamsFile {
+            version: PARAMS_VERSION,
+            modulus: encode_bytes(&modulus_be::<F>()),
+            width: T,
+            alpha: self.alpha,
+            num_full_rounds: self.num_full_rounds,
+            num_partial_rounds: self.num_partial_rounds,
+            hash_mode: self.hash_mode,
+            round_constants: self.round_keys.iter().map(encode).collect(),
+            mds_matrix: self
+                .mds_matrix
+                .iter()
+                .map(|row| row.iter().map(encode).collect())
+                .collect(),
+        }
+    }
+}
+
+// Big-endian bytes of p, of the length of the field encoding
+fn modulus_be<F: PrimeField>() -> Vec<u8> {
+    let mut bytes = modulus_minus_one_be::<F>();
+    for byte in bytes.iter_mut().rev() {
+        let (sum, carry) = byte.overflowing_add(1);
+        *byte = sum;
+        if !carry {
+            break;
+        }
+    }
+
+    bytes
+}
+
+fn trim_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let num_zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+    &bytes[num_zeros..]
+}
+
+fn encode<F: PrimeField>(x: &F) -> String {
+    encode_bytes(&to_bytes_be(x))
+}
+
+fn encode_bytes(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("0x{}", digits)
+}
+
+// Only canonical encodings of the full length are accepted
+fn decode<F: PrimeField>(element: &str) -> Result<F, ParamsError> {
+    parse_hex(element)
+        .and_then(|bytes| from_bytes_be(&bytes))
+        .ok_or_else(|| ParamsError::NonCanonical(element.to_string()))
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits = hex.strip_prefix("0x")?;
+    if digits.len() % 2 != 0 || !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..(i + 2)], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon::Poseidon;
+    use crate::spec::{Bls12_381, Bn254, Pallas, PoseidonSpec, Secq256k1, Vesta, K256};
+    use halo2curves::bn256::Fr;
+    use halo2curves::pasta;
+    use halo2curves::secp256k1::{Fp, Fq};
+    use std::sync::Arc;
+
+    const K256_PARAMS: &str = include_str!("../../params/k256_t3.json");
+
+    #[test]
+    fn test_k256_export() {
+        let constants: Arc<PoseidonConstants<Fp, 3>> = K256::constants();
+        assert_eq!(constants.to_json(), K256_PARAMS.trim_end());
+
+        let loaded = PoseidonConstants::<Fp, 3>::from_json(K256_PARAMS).unwrap();
+        assert_eq!(loaded.round_keys, constants.round_keys);
+        assert_eq!(loaded.mds_matrix, constants.mds_matrix);
+        assert_eq!(loaded.num_partial_rounds, constants.num_partial_rounds);
+
+        let loaded = PoseidonConstants::<Fp, 3>::from_toml(&constants.to_toml()).unwrap();
+        assert_eq!(loaded.round_keys, constants.round_keys);
+        assert_eq!(loaded.mds_matrix, constants.mds_matrix);
+    }
+
+    // Export and reload the constants, which must hash the same
+    fn check_reload<F: PrimeField, S: PoseidonSpec<F, T>, const T: usize>() {
+        let constants = S::constants();
+        let input: Vec<F> = (1..T as u64).map(F::from).collect();
+        let expected = Poseidon::new(constants.clone(), [F::zero(); T]).hash(&input);
+
+        for loaded in [
+            PoseidonConstants::<F, T>::from_json(&constants.to_json()).unwrap(),
+            PoseidonConstants::<F, T>::from_toml(&constants.to_toml()).unwrap(),
+        ] {
+            assert_eq!(loaded.hash_mode, constants.hash_mode);
+
+            let mut poseidon = Poseidon::new(Arc::new(loaded), [F::zero(); T]);
+            assert_eq!(poseidon.hash(&input), expected);
+        }
+    }
+
+    #[test]
+    fn test_reload_specs() {
+        check_reload::<Fp, K256, 3>();
+        check_reload::<Fq, Secq256k1, 3>();
+        check_reload::<Fr, Bn254, 3>();
+        check_reload::<Fr, Bn254, 5>();
+        check_reload::<pasta::Fp, Pallas, 3>();
+        check_reload::<pasta::Fq, Vesta, 3>();
+        check_reload::<bls12_381::Scalar, Bls12_381, 3>();
+    }
+
+    fn load_modified(modify: impl FnOnce(&mut ParamsFile)) -> Result<(), ParamsError> {
+        let mut file: ParamsFile = serde_json::from_str(K256_PARAMS).unwrap();
+        modify(&mut file);
+
+        PoseidonConstants::<Fp, 3>::from_json(&serde_json::to_string(&file).unwrap()).map(|_| ())
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        assert!(matches!(
+            PoseidonConstants::<Fp, 3>::from_json("{}"),
+            Err(ParamsError::Syntax(_))
+        ));
+        assert!(matches!(
+            PoseidonConstants::<Fr, 3>::from_json(K256_PARAMS),
+            Err(ParamsError::ModulusMismatch { .. })
+        ));
+        assert_eq!(
+            PoseidonConstants::<Fp, 5>::from_json(K256_PARAMS).err(),
+            Some(ParamsError::WidthMismatch {
+                expected: 5,
+                found: 3
+            })
+        );
+
+        assert_eq!(
+            load_modified(|file| file.version = 1),
+            Err(ParamsError::UnsupportedVersion(1))
+        );
+        assert_eq!(
+            load_modified(|file| file.alpha = 3),
+            Err(ParamsError::InvalidAlpha(3))
+        );
+        assert_eq!(
+            load_modified(|file| file.num_full_rounds = 7),
+            Err(ParamsError::OddFullRounds(7))
+        );
+        assert_eq!(
+            load_modified(|file| file.num_partial_rounds = 57),
+            Err(ParamsError::RoundConstantCount {
+                expected: 195,
+                found: 192
+            })
+        );
+        assert_eq!(
+            load_modified(|file| file.mds_matrix.truncate(2)),
+            Err(ParamsError::MdsMatrixShape { width: 3 })
+        );
+
+        // The modulus itself, a short encoding and a sign are not canonical
+        let modulus = encode_bytes(&modulus_be::<Fp>());
+        for element in [
+            modulus,
+            "0x01".to_string(),
+            format!("0x+{}", "0".repeat(63)),
+        ] {
+            assert_eq!(
+                load_modified(|file| file.round_constants[0] = element.clone()),
+                Err(ParamsError::NonCanonical(element))
+            );
+        }
+
+        assert!(matches!(
+            load_modified(|file| file.mds_matrix[1] = file.mds_matrix[0].clone()),
+            Err(ParamsError::Mds(MdsError::NotMds { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_wide_params() {
+        // A matrix too wide to check every submatrix of is rejected at once,
+        // unless it's a Cauchy matrix like those of the parameter sets
+        let constants: Arc<PoseidonConstants<Fp, 16>> = K256::constants();
+        let json = constants.to_json();
+        assert!(PoseidonConstants::<Fp, 16>::from_json(&json).is_ok());
+
+        let mut file: ParamsFile = serde_json::from_str(&json).unwrap();
+        file.mds_matrix[0][0] = file.mds_matrix[0][1].clone();
+        assert_eq!(
+            PoseidonConstants::<Fp, 16>::from_json(&serde_json::to_string(&file).unwrap()).err(),
+            Some(ParamsError::Mds(MdsError::UncheckedWidth {
+                width: 16,
+                max: 8
+            }))
+        );
+    }
+}
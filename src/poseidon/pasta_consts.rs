@@ -0,0 +1,3 @@
+// The P128Pow5T3 round numbers of halo2_gadgets, for Pallas and Vesta.
+pub const NUM_FULL_ROUNDS: usize = 8;
+pub const NUM_PARTIAL_ROUNDS: usize = 56;
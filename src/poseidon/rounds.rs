@@ -0,0 +1,191 @@
+// Round numbers as computed by calc_round_numbers.py from the reference
+// implementation of the Poseidon paper (https://extgit.iaik.tugraz.at/krypto/hadeshash).
+use super::modulus_minus_one_be;
+use ff::PrimeField;
+
+/// log2 of the modulus of `F`.
+pub fn log2_modulus<F: PrimeField>() -> f64 {
+    let bytes = modulus_minus_one_be::<F>();
+    let bytes: Vec<u8> = bytes.into_iter().skip_while(|byte| *byte == 0).collect();
+
+    // The leading 8 bytes are enough for the precision of an f64
+    let (leading, rest) = bytes.split_at(bytes.len().min(8));
+    let leading = leading
+        .iter()
+        .fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+
+    (leading as f64).log2() + (rest.len() * 8) as f64
+}
+
+/// Compute the smallest numbers of full and partial rounds of a Poseidon
+/// instance that resist the statistical, interpolation and Gröbner basis
+/// attacks at `security_level` bits (usually 80, 128 or 256), for a field with
+/// a modulus of `log2_modulus` bits and an x^alpha S-box. The result includes
+/// the usual security margin of two full rounds and 7.5% more partial rounds.
+pub fn round_numbers(
+    log2_modulus: f64,
+    width: usize,
+    alpha: u64,
+    security_level: u32,
+) -> (usize, usize) {
+    assert!(width >= 2, "the width must be at least 2");
+    assert!(alpha > 1, "the S-box exponent must be at least 2");
+
+    let mut num_full_rounds = 0;
+    let mut num_partial_rounds = 0;
+    let mut min_cost = usize::MAX;
+
+    // Minimize the number of S-boxes
+    for r_p in 1..500 {
+        let mut r_p = r_p;
+        for r_f in (4..100).step_by(2) {
+            if !is_secure(log2_modulus, width, r_f, r_p, alpha, security_level) {
+                continue;
+            }
+
+            // The reference script keeps the increased number of partial rounds
+            // for the rest of the inner loop, and so do we to get the same result.
+            let r_f = r_f + 2;
+            r_p = (r_p as f64 * 1.075).ceil() as usize;
+
+            let cost = width * r_f + r_p;
+            if cost < min_cost || (cost == min_cost && r_f < num_full_rounds) {
+                num_full_rounds = r_f;
+                num_partial_rounds = r_p;
+                min_cost = cost;
+            }
+        }
+    }
+
+    (num_full_rounds, num_partial_rounds)
+}
+
+fn is_secure(
+    log2_modulus: f64,
+    width: usize,
+    num_full_rounds: usize,
+    num_partial_rounds: usize,
+    alpha: u64,
+    security_level: u32,
+) -> bool {
+    let n = log2_modulus.ceil();
+    let t = width as f64;
+    let r_f = num_full_rounds as f64;
+    let r_p = num_partial_rounds as f64;
+    let m = security_level as f64;
+    let alpha = alpha as f64;
+    let log_alpha_2 = 1.0 / alpha.log2();
+
+    // Statistical attacks
+    let r_f_1 = if m <= (log2_modulus - (alpha - 1.0) / 2.0).floor() * (t + 1.0) {
+        6.0
+    } else {
+        10.0
+    };
+
+    // Interpolation attacks
+    let r_f_2 = 1.0 + (log_alpha_2 * m.min(n)).ceil() + (t.ln() / alpha.ln()).ceil() - r_p;
+
+    // Gröbner basis attacks
+    let r_f_3 = log_alpha_2 * m.min(log2_modulus) - r_p;
+    let r_f_4 = t - 1.0 + log_alpha_2 * (m / (t + 1.0)).min(log2_modulus / 2.0) - r_p;
+    let r_f_5 = (t - 2.0 + m / (2.0 * alpha.log2()) - r_p) / (t - 1.0);
+
+    let r_f_max = [r_f_1, r_f_2, r_f_3, r_f_4, r_f_5]
+        .iter()
+        .map(|x| x.ceil())
+        .fold(f64::MIN, f64::max);
+    if r_f < r_f_max {
+        return false;
+    }
+
+    // Gröbner basis attack that skips the first partial rounds, added to the
+    // reference script in 2023
+    let r_temp = (t / 3.0).floor();
+    let over = (r_f - 1.0) * t + r_p + r_temp + r_temp * (r_f / 2.0) + r_p + alpha;
+    let under = r_temp * (r_f / 2.0) + r_p + alpha;
+
+    (2.0 * log2_binomial(over as u64, under as u64)).ceil() >= m
+}
+
+// log2 of n choose k
+fn log2_binomial(n: u64, k: u64) -> f64 {
+    (ln_factorial(n) - ln_factorial(k) - ln_factorial(n - k)) / std::f64::consts::LN_2
+}
+
+// ln(n!), with Stirling's series for large n
+fn ln_factorial(n: u64) -> f64 {
+    if n < 16 {
+        return (2..=n).map(|i| (i as f64).ln()).sum();
+    }
+
+    let n = n as f64;
+    n * n.ln() - n + 0.5 * (2.0 * std::f64::consts::PI * n).ln() + 1.0 / (12.0 * n)
+        - 1.0 / (360.0 * n.powi(3))
+        + 1.0 / (1260.0 * n.powi(5))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon::{bn254_consts, k256_consts};
+    use halo2curves::bn256::Fr;
+    use halo2curves::secp256k1::Fp;
+
+    #[test]
+    fn test_k256_round_numbers() {
+        let log2_p = log2_modulus::<Fp>();
+
+        assert_eq!(
+            round_numbers(log2_p, 3, 5, 128),
+            (
+                k256_consts::NUM_FULL_ROUNDS,
+                k256_consts::NUM_PARTIAL_ROUNDS
+            )
+        );
+
+        let expected = [(80, 34, 35), (128, 56, 57), (256, 116, 117)];
+        for (security_level, r_p_small, r_p_large) in expected {
+            for width in 2..=16 {
+                let r_p = if width <= 5 { r_p_small } else { r_p_large };
+                assert_eq!(round_numbers(log2_p, width, 5, security_level), (8, r_p));
+            }
+        }
+    }
+
+    #[test]
+    fn test_bn254_round_numbers() {
+        // circomlib rounds the number of partial rounds up to a multiple of the width
+        for width in 2..=17 {
+            let (num_full_rounds, num_partial_rounds) =
+                round_numbers(log2_modulus::<Fr>(), width, 5, 128);
+
+            assert_eq!(num_full_rounds, bn254_consts::NUM_FULL_ROUNDS);
+            assert_eq!(
+                (num_partial_rounds + width - 1) / width * width,
+                bn254_consts::NUM_PARTIAL_ROUNDS_BY_WIDTH[width - 2]
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_numbers() {
+        // BN254 at 256 bits and Goldilocks with x^7, as used by Plonky2
+        assert_eq!(round_numbers(log2_modulus::<Fr>(), 3, 5, 256), (8, 114));
+        assert_eq!(round_numbers(64.0, 12, 7, 128), (8, 22));
+        assert_eq!(round_numbers(log2_modulus::<Fp>(), 3, 11, 128), (8, 37));
+    }
+
+    #[test]
+    fn test_log2_binomial() {
+        let exact = |n: u64, k: u64| -> f64 {
+            (1..=k)
+                .map(|i| ((n - k + i) as f64).log2() - (i as f64).log2())
+                .sum()
+        };
+
+        for (n, k) in [(10, 3), (20, 10), (100, 1), (700, 300), (1500, 600)] {
+            assert!((log2_binomial(n, k) - exact(n, k)).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// The x^alpha S-box. It's a permutation of F only if gcd(alpha, p - 1) = 1.
+use super::modulus_minus_one_be;
+use ff::PrimeField;
+
+pub(crate) fn is_valid_alpha<F: PrimeField>(alpha: u64) -> bool {
+    alpha > 1 && gcd(alpha, modulus_minus_one_rem::<F>(alpha)) == 1
+}
+
+pub(crate) fn validate_alpha<F: PrimeField>(alpha: u64) {
+    assert!(alpha > 1, "the S-box exponent must be at least 2");
+    assert_eq!(
+        gcd(alpha, modulus_minus_one_rem::<F>(alpha)),
+        1,
+        "the S-box exponent must be coprime to p - 1"
+    );
+}
+
+// x^alpha with a fixed addition chain for the usual exponents, and a
+// square-and-multiply over the public bits of alpha otherwise. Unlike
+// `pow_vartime`, the sequence of operations only depends on alpha, never on x.
+pub(crate) fn pow<F: PrimeField>(x: F, alpha: u64) -> F {
+    match alpha {
+        3 => x.square() * x,
+        5 => x.square().square() * x,
+        7 => {
+            let x2 = x.square();
+            x2.square() * x2 * x
+        }
+        11 => {
+            let x2 = x.square();
+            x2.square().square() * x2 * x
+        }
+        _ => {
+            let num_bits = 64 - alpha.leading_zeros();
+            (0..(num_bits - 1)).rev().fold(x, |acc, i| {
+                let acc = acc.square();
+                if (alpha >> i) & 1 == 1 {
+                    acc * x
+                } else {
+                    acc
+                }
+            })
+        }
+    }
+}
+
+// x^exponent for a big-endian exponent, with a square-and-multiply over its
+// public bits
+pub(crate) fn pow_be<F: PrimeField>(x: F, exponent: &[u8]) -> F {
+    exponent.iter().fold(F::one(), |acc, byte| {
+        (0..8).rev().fold(acc, |acc, i| {
+            let acc = acc.square();
+            if (byte >> i) & 1 == 1 {
+                acc * x
+            } else {
+                acc
+            }
+        })
+    })
+}
+
+// The big-endian bytes of 1/alpha modulo p - 1, the exponent of the inverse
+// S-box. It's (k * (p - 1) + 1) / alpha for the k < alpha that makes the
+// division exact.
+pub(crate) fn inverse_alpha_be<F: PrimeField>(alpha: u64) -> Vec<u8> {
+    validate_alpha::<F>(alpha);
+
+    let remainder = modulus_minus_one_rem::<F>(alpha) as u128;
+    let k = (1..alpha as u128)
+        .find(|k| (k * remainder + 1) % alpha as u128 == 0)
+        .unwrap();
+
+    // k * (p - 1) + 1, with leading bytes for the carry
+    let mut numerator = vec![0u8; 8];
+    numerator.extend(modulus_minus_one_be::<F>());
+    let mut carry = 1u128;
+    for byte in numerator.iter_mut().rev() {
+        let x = *byte as u128 * k + carry;
+        *byte = x as u8;
+        carry = x >> 8;
+    }
+
+    let mut remainder = 0u128;
+    for byte in numerator.iter_mut() {
+        let x = (remainder << 8) + *byte as u128;
+        *byte = (x / alpha as u128) as u8;
+        remainder = x % alpha as u128;
+    }
+
+    numerator
+}
+
+// (p - 1) mod m
+fn modulus_minus_one_rem<F: PrimeField>(m: u64) -> u64 {
+    modulus_minus_one_be::<F>()
+        .iter()
+        .fold(0u128, |acc, byte| ((acc << 8) + *byte as u128) % m as u128) as u64
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use halo2curves::secp256k1::Fp;
+
+    #[test]
+    fn test_pow() {
+        let mut rng = rand::thread_rng();
+        let x = Fp::random(&mut rng);
+
+        for alpha in [2, 3, 5, 7, 11, 13, 17, 257] {
+            assert_eq!(pow(x, alpha), x.pow_vartime([alpha, 0, 0, 0]));
+        }
+    }
+
+    #[test]
+    fn test_inverse_alpha() {
+        let mut rng = rand::thread_rng();
+        let x = Fp::random(&mut rng);
+
+        for alpha in [5, 11, 13] {
+            assert_eq!(pow_be(pow(x, alpha), &inverse_alpha_be::<Fp>(alpha)), x);
+        }
+    }
+
+    #[test]
+    fn test_validate_alpha() {
+        // p - 1 of secp256k1 is divisible by 3 and 7
+        assert_eq!(modulus_minus_one_rem::<Fp>(3), 0);
+        assert_eq!(modulus_minus_one_rem::<Fp>(7), 0);
+
+        validate_alpha::<Fp>(5);
+        validate_alpha::<Fp>(11);
+        assert!(std::panic::catch_unwind(|| validate_alpha::<Fp>(3)).is_err());
+    }
+}
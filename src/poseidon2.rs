@@ -0,0 +1,498 @@
+// Poseidon2 (https://eprint.iacr.org/2023/323), instantiated for the widths 2 and
+// 3, where the internal matrix doesn't need to be sampled, and for the multiples
+// of 4 up to 24, whose external matrix is built from the 4x4 matrix M4 of the
+// paper.
+use crate::poseidon::grain::Grain;
+use crate::poseidon::{log2_modulus, matrix, mds, round_numbers, sbox};
+use ff::PrimeField;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Poseidon2Error {
+    // The linear layers are only defined for the widths 2, 3, 4, 8, 12, 16, 20
+    // and 24
+    UnsupportedWidth(usize),
+    InvalidAlpha(u64),
+    RoundConstantCount { expected: usize, found: usize },
+}
+
+impl fmt::Display for Poseidon2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Poseidon2Error::UnsupportedWidth(width) => {
+                write!(f, "Poseidon2 is not defined for the width {}", width)
+            }
+            Poseidon2Error::InvalidAlpha(alpha) => {
+                write!(f, "x^{} is not a permutation of the field", alpha)
+            }
+            Poseidon2Error::RoundConstantCount { expected, found } => {
+                write!(f, "expected {} round constants, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Poseidon2Error {}
+
+/// Whether Poseidon2 is defined for the width.
+pub fn is_supported_width(width: usize) -> bool {
+    matches!(width, 2 | 3) || (width % 4 == 0 && (4..=24).contains(&width))
+}
+
+pub struct Poseidon2Constants<F: PrimeField, const T: usize> {
+    // t constants per full round and one constant per partial round
+    pub round_keys: Vec<F>,
+    // The internal matrix is the all-ones matrix plus this diagonal
+    pub internal_diagonal: [F; T],
+    // S-box exponent
+    pub alpha: u64,
+    pub num_full_rounds: usize,
+    pub num_partial_rounds: usize,
+}
+
+impl<F: PrimeField, const T: usize> Poseidon2Constants<F, T> {
+    // Panics if the width, the S-box exponent or the number of round constants
+    // is invalid
+    pub fn new(
+        round_constants: Vec<F>,
+        internal_diagonal: [F; T],
+        alpha: u64,
+        num_full_rounds: usize,
+        num_partial_rounds: usize,
+    ) -> Self {
+        Self::try_new(
+            round_constants,
+            internal_diagonal,
+            alpha,
+            num_full_rounds,
+            num_partial_rounds,
+        )
+        .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_new(
+        round_constants: Vec<F>,
+        internal_diagonal: [F; T],
+        alpha: u64,
+        num_full_rounds: usize,
+        num_partial_rounds: usize,
+    ) -> Result<Self, Poseidon2Error> {
+        if !is_supported_width(T) {
+            return Err(Poseidon2Error::UnsupportedWidth(T));
+        }
+        if !sbox::is_valid_alpha::<F>(alpha) {
+            return Err(Poseidon2Error::InvalidAlpha(alpha));
+        }
+
+        // There must be T round constants per full round and one per partial round
+        let expected = num_full_rounds * T + num_partial_rounds;
+        if round_constants.len() != expected {
+            return Err(Poseidon2Error::RoundConstantCount {
+                expected,
+                found: round_constants.len(),
+            });
+        }
+
+        Ok(Self {
+            num_full_rounds,
+            num_partial_rounds,
+            internal_diagonal,
+            alpha,
+            round_keys: round_constants,
+        })
+    }
+}
+
+/// Generate the constants of a Poseidon2 instance over `F` with the width `T`
+/// and the given S-box exponent and number of rounds. The round constants are
+/// those of the reference `poseidon2_rust_params.sage` script. For the widths 2
+/// and 3 the internal matrix is fixed, so the constants are those of the
+/// reference instances. For the larger widths the reference script samples the
+/// internal diagonal at random, so it's drawn from the Grain LFSR after the
+/// round constants instead, until the internal matrix is invertible and passes
+/// the subspace trail checks of the Poseidon MDS matrices. The diagonal of a
+/// reference instance can be passed to [`Poseidon2Constants::new`].
+pub fn generate_constants<F: PrimeField, const T: usize>(
+    alpha: u64,
+    num_full_rounds: usize,
+    num_partial_rounds: usize,
+) -> Result<Poseidon2Constants<F, T>, Poseidon2Error> {
+    if !is_supported_width(T) {
+        return Err(Poseidon2Error::UnsupportedWidth(T));
+    }
+
+    let mut grain = Grain::new(F::NUM_BITS, T, num_full_rounds, num_partial_rounds);
+
+    let num_constants = num_full_rounds * T + num_partial_rounds;
+    let round_constants = (0..num_constants)
+        .map(|_| grain.next_field_element())
+        .collect();
+
+    // M_I = [[2, 1], [1, 3]] and M_I = [[2, 1, 1], [1, 2, 1], [1, 1, 3]]
+    let internal_diagonal = if T <= 3 {
+        std::array::from_fn(|i| if i == T - 1 { F::from(2) } else { F::one() })
+    } else {
+        generate_internal_diagonal(&mut grain)
+    };
+
+    Poseidon2Constants::try_new(
+        round_constants,
+        internal_diagonal,
+        alpha,
+        num_full_rounds,
+        num_partial_rounds,
+    )
+}
+
+fn generate_internal_diagonal<F: PrimeField, const T: usize>(grain: &mut Grain) -> [F; T] {
+    loop {
+        let diagonal: [F; T] = std::array::from_fn(|_| grain.next_field_element());
+
+        let internal_matrix: Vec<Vec<F>> = (0..T)
+            .map(|i| {
+                (0..T)
+                    .map(|j| {
+                        if i == j {
+                            F::one() + diagonal[i]
+                        } else {
+                            F::one()
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        if matrix::invert(&internal_matrix).is_some()
+            && mds::validate_partial_round_matrix(&internal_matrix).is_ok()
+        {
+            return diagonal;
+        }
+    }
+}
+
+/// Generate the constants of a Poseidon2 instance over `F` with the width `T`
+/// and the given S-box exponent, with the round numbers given by
+/// [`round_numbers`] for the security level in bits.
+pub fn generate_secure_constants<F: PrimeField, const T: usize>(
+    alpha: u64,
+    security_level: u32,
+) -> Result<Poseidon2Constants<F, T>, Poseidon2Error> {
+    let (num_full_rounds, num_partial_rounds) =
+        round_numbers(log2_modulus::<F>(), T, alpha, security_level);
+
+    generate_constants(alpha, num_full_rounds, num_partial_rounds)
+}
+
+pub struct Poseidon2<F: PrimeField, const T: usize> {
+    pub state: [F; T],
+    pub constants: Arc<Poseidon2Constants<F, T>>,
+    pub pos: usize,
+}
+
+impl<F: PrimeField, const T: usize> Poseidon2<F, T> {
+    pub fn new(constants: Arc<Poseidon2Constants<F, T>>, state: [F; T]) -> Self {
+        Self {
+            state,
+            constants,
+            pos: 0,
+        }
+    }
+
+    /// Permute the state, in a time that doesn't depend on the state like
+    /// [`Poseidon::permute`].
+    ///
+    /// [`Poseidon::permute`]: crate::poseidon::Poseidon::permute
+    pub fn permute(&mut self) {
+        let full_rounds_half = self.constants.num_full_rounds / 2;
+
+        // Initial linear layer
+        self.external_matrix_mul();
+
+        // First half of full rounds
+        for _ in 0..full_rounds_half {
+            self.full_round();
+        }
+
+        // Partial rounds
+        for _ in 0..self.constants.num_partial_rounds {
+            self.partial_round();
+        }
+
+        // Second half of full rounds
+        for _ in 0..full_rounds_half {
+            self.full_round();
+        }
+
+        self.pos = 0;
+    }
+
+    fn sum(&self) -> F {
+        self.state.iter().fold(F::zero(), |acc, x| acc + x)
+    }
+
+    // Multiplication by circ(2, 1) or circ(2, 1, 1) for the widths 2 and 3, by
+    // M4 for the width 4, and by circ(2 M4, M4, ..., M4) for the larger widths
+    fn external_matrix_mul(&mut self) {
+        if T < 4 {
+            let sum = self.sum();
+            for x in self.state.iter_mut() {
+                *x += sum;
+            }
+            return;
+        }
+
+        for chunk in self.state.chunks_exact_mut(4) {
+            m4_mul(chunk);
+        }
+
+        if T > 4 {
+            // Add to each element the sum of the elements at the same position
+            // of every chunk
+            let mut sums = [F::zero(); 4];
+            for chunk in self.state.chunks_exact(4) {
+                for (sum, x) in sums.iter_mut().zip(chunk.iter()) {
+                    *sum += x;
+                }
+            }
+
+            for chunk in self.state.chunks_exact_mut(4) {
+                for (x, sum) in chunk.iter_mut().zip(sums.iter()) {
+                    *x += sum;
+                }
+            }
+        }
+    }
+
+    // Multiplication by the all-ones matrix plus the internal diagonal
+    fn internal_matrix_mul(&mut self) {
+        let sum = self.sum();
+        for (x, d) in self
+            .state
+            .iter_mut()
+            .zip(self.constants.internal_diagonal.iter())
+        {
+            *x = *x * d + sum;
+        }
+    }
+
+    fn full_round(&mut self) {
+        for (x, c) in self
+            .state
+            .iter_mut()
+            .zip(self.constants.round_keys[self.pos..].iter())
+        {
+            *x += c;
+            *x = sbox::pow(*x, self.constants.alpha);
+        }
+
+        self.external_matrix_mul();
+
+        self.pos += T;
+    }
+
+    fn partial_round(&mut self) {
+        self.state[0] += self.constants.round_keys[self.pos];
+        self.state[0] = sbox::pow(self.state[0], self.constants.alpha);
+
+        self.internal_matrix_mul();
+
+        self.pos += 1;
+    }
+}
+
+// Multiplication of four elements by the matrix
+// M4 = [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]]
+// with the additions and doublings of the paper
+fn m4_mul<F: PrimeField>(x: &mut [F]) {
+    let t0 = x[0] + x[1];
+    let t1 = x[2] + x[3];
+    let t2 = x[1].double() + t1;
+    let t3 = x[3].double() + t0;
+    let t4 = t1.double().double() + t3;
+    let t5 = t0.double().double() + t2;
+    let t6 = t3 + t5;
+    let t7 = t2 + t4;
+
+    x[0] = t6;
+    x[1] = t5;
+    x[2] = t7;
+    x[3] = t4;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::SECURITY_LEVEL;
+    use bls12_381::Scalar;
+    use halo2curves::bn256::Fr;
+    use halo2curves::secp256k1::Fp;
+
+    #[test]
+    fn test_bn254() {
+        // From the reference implementation at https://github.com/HorizenLabs/poseidon2
+        let constants = generate_constants::<Fr, 3>(5, 8, 56).unwrap();
+        let state = [Fr::from(0), Fr::from(1), Fr::from(2)];
+        let mut poseidon2 = Poseidon2::new(Arc::new(constants), state);
+
+        poseidon2.permute();
+
+        let expected = [
+            "5297208644449048816064511434384511824916970985131888684874823260532015509555",
+            "21816030159894113985964609355246484851575571273661473159848781012394295965040",
+            "13940986381491601233448981668101586453321811870310341844570924906201623195336",
+        ];
+        let expected: Vec<Fr> = expected
+            .iter()
+            .map(|x| Fr::from_str_vartime(x).unwrap())
+            .collect();
+        assert_eq!(poseidon2.state.to_vec(), expected);
+    }
+
+    fn permute_k256<const T: usize>() -> [Fp; T] {
+        let constants = generate_secure_constants::<Fp, T>(5, SECURITY_LEVEL).unwrap();
+        let state = std::array::from_fn(|i| Fp::from(i as u64));
+        let mut poseidon2 = Poseidon2::new(Arc::new(constants), state);
+
+        poseidon2.permute();
+
+        poseidon2.state
+    }
+
+    #[test]
+    fn test_k256() {
+        let expected = [
+            vec![
+                "25719160181350019191964706423655770150120477446108152275071168457832510334803",
+                "100438333318494556803209047266247540783923233081905330209177693384377871230046",
+            ],
+            vec![
+                "51682984177619898557665181435368014018678553241918531446217248422913705195956",
+                "14968038307345328072734245424096249827577142812722161760026332172578534888897",
+                "22591336048360040809864772835552798825068371258589340593960972252936287611824",
+            ],
+        ];
+        let states = [permute_k256::<2>().to_vec(), permute_k256::<3>().to_vec()];
+
+        for (state, expected) in states.into_iter().zip(expected) {
+            let expected: Vec<Fp> = expected
+                .iter()
+                .map(|x| Fp::from_str_vartime(x).unwrap())
+                .collect();
+            assert_eq!(state, expected);
+        }
+    }
+
+    fn permute_bls12_381<const T: usize>(constants: Poseidon2Constants<Scalar, T>) -> Vec<Scalar> {
+        let state = std::array::from_fn(|i| Scalar::from(i as u64));
+        let mut poseidon2 = Poseidon2::new(Arc::new(constants), state);
+
+        poseidon2.permute();
+
+        poseidon2.state.to_vec()
+    }
+
+    #[test]
+    fn test_bls12_381() {
+        // From the reference implementation at https://github.com/HorizenLabs/poseidon2,
+        // whose internal diagonal for the width 4 is MAT_DIAG4_M_1 of
+        // poseidon2_instance_bls12.rs
+        let internal_diagonal = [
+            "3318655301660913042182619814759206048255383170476851554953749170663204936509",
+            "44109141949870474831175246250426841318460684547410744873114578168622501784228",
+            "29659240597933972492294679304149537656111319867730125083673280425768884331730",
+            "22737209252563391582737936950219322780303060945177735953720593600577551054727",
+        ]
+        .map(|x| Scalar::from_str_vartime(x).unwrap());
+        let round_constants = generate_constants::<Scalar, 4>(5, 8, 56)
+            .unwrap()
+            .round_keys;
+        let constants = Poseidon2Constants::new(round_constants, internal_diagonal, 5, 8, 56);
+
+        let expected = [
+            vec![
+                "52363037649740716030766703085959398089247109082552119369664912680823987490382",
+                "14046768006860121393795564977526877746513733133602551947789337117320794882996",
+            ],
+            vec![
+                "12249794248008371943965195507194171345311258135909954438653399945366036280816",
+                "34530512148863939455675662080244961267438888698342943852832905748800656324753",
+                "14376704056201869785286496167433956486206399269351247466538836868879344524395",
+            ],
+            vec![
+                "18543807459877364126908352029685462281488659954858558933392966590401135434349",
+                "6486003883477478427469307271780113565813206904808083202373087381132280912685",
+                "49531065568108882186803339532638377985898634706171140442815716917306497904848",
+                "3533173620704471731420930490618911963424635467018145440601039502713526898230",
+            ],
+        ];
+        let states = [
+            permute_bls12_381(generate_constants::<Scalar, 2>(5, 8, 56).unwrap()),
+            permute_bls12_381(generate_constants::<Scalar, 3>(5, 8, 56).unwrap()),
+            permute_bls12_381(constants),
+        ];
+
+        for (state, expected) in states.into_iter().zip(expected) {
+            let expected: Vec<Scalar> = expected
+                .iter()
+                .map(|x| Scalar::from_str_vartime(x).unwrap())
+                .collect();
+            assert_eq!(state, expected);
+        }
+    }
+
+    #[test]
+    fn test_external_matrix() {
+        // circ(2 M4, M4) for the width 8
+        let m4: [[u64; 4]; 4] = [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]];
+        let state: [Fr; 8] = std::array::from_fn(|i| Fr::from(i as u64 + 1));
+
+        let constants = generate_constants::<Fr, 8>(5, 8, 56).unwrap();
+        let mut poseidon2 = Poseidon2::new(Arc::new(constants), state);
+        poseidon2.external_matrix_mul();
+
+        let expected: Vec<Fr> = (0..8)
+            .map(|i| {
+                (0..8)
+                    .map(|j| {
+                        let factor = if i / 4 == j / 4 { 2 } else { 1 };
+                        Fr::from(factor * m4[i % 4][j % 4]) * state[j]
+                    })
+                    .fold(Fr::zero(), |acc, x| acc + x)
+            })
+            .collect();
+        assert_eq!(poseidon2.state.to_vec(), expected);
+    }
+
+    #[test]
+    fn test_unsupported_width() {
+        assert_eq!(
+            generate_constants::<Fr, 5>(5, 8, 56).err(),
+            Some(Poseidon2Error::UnsupportedWidth(5))
+        );
+        assert_eq!(
+            generate_constants::<Fr, 28>(5, 8, 56).err(),
+            Some(Poseidon2Error::UnsupportedWidth(28))
+        );
+
+        let try_new = |alpha, num_partial_rounds| {
+            Poseidon2Constants::<Fr, 3>::try_new(
+                vec![Fr::zero(); 3],
+                [Fr::one(); 3],
+                alpha,
+                1,
+                num_partial_rounds,
+            )
+            .err()
+        };
+        assert_eq!(try_new(5, 0), None);
+        assert_eq!(
+            try_new(5, 1),
+            Some(Poseidon2Error::RoundConstantCount {
+                expected: 4,
+                found: 3
+            })
+        );
+        assert_eq!(try_new(3, 0), Some(Poseidon2Error::InvalidAlpha(3)));
+    }
+}
@@ -0,0 +1,775 @@
+// Regression tests of every parameter set, so that a change of the outputs of
+// the permutation, of `Poseidon::hash` or of the sponge doesn't go unnoticed.
+// The inputs are 0, 1, ... for the permutation, 1, 2, ... for the hash and the
+// absorbed elements, and the domain separator is "test".
+//
+// Only these vectors are published by another implementation:
+// - the permutations of the hadeshash reference implementation
+//   (https://extgit.iaik.tugraz.at/krypto/hadeshash) in `test_hadeshash`. They
+//   cover the Bn254 parameter set for the widths 3 and 5. The BLS12-381 ones
+//   use R_P = 57 and 60, so they check the constant generation but not the
+//   Bls12_381 parameter set, which has R_P = 56.
+// - the Bn254 permutations of `test_parameter_sets`, which are the same, and
+//   its Bn254 hashes, poseidon([1, 2]) and poseidon([1, 2, 3, 4]) of the
+//   circomlibjs tests.
+// - the Pallas and Vesta permutations of `test_parameter_sets`, from
+//   test_against_reference of halo2_gadgets' p128pow5t3.rs.
+// - the SHA3-256 prefixes of the empty string and of "abc" in `test_tag`.
+// All the other vectors, including every sponge output, were computed by this
+// crate and reproduced by a separate port of the reference scripts and of the
+// sponge, not by the hadeshash sage scripts, so they aren't known answers.
+use crate::poseidon::{generate_constants, Poseidon, PoseidonConstants};
+use crate::spec::{Bls12_381, Bn254, Pallas, PoseidonSpec, Secq256k1, Vesta, K256};
+use crate::sponge::{AbsorbMode, IOPattern, PoseidonSponge, SpongeOp};
+use bls12_381::Scalar;
+use ff::PrimeField;
+use halo2curves::bn256::Fr;
+use halo2curves::pasta;
+use halo2curves::secp256k1::{Fp, Fq};
+use std::sync::Arc;
+
+fn to_elements<F: PrimeField>(x: &[&str]) -> Vec<F> {
+    x.iter().map(|x| F::from_str_vartime(x).unwrap()).collect()
+}
+
+// The permutation of 0, 1, ..., t - 1
+fn check_permutation<F: PrimeField, const T: usize>(
+    constants: Arc<PoseidonConstants<F, T>>,
+    expected: &[&str],
+) {
+    let mut poseidon = Poseidon::new(constants, std::array::from_fn(|i| F::from(i as u64)));
+    poseidon.permute();
+
+    assert_eq!(poseidon.state.to_vec(), to_elements::<F>(expected));
+}
+
+fn check_spec<F: PrimeField, S: PoseidonSpec<F, T>, const T: usize>(
+    permutation: &[&str],
+    hash: &str,
+) {
+    check_permutation(S::constants(), permutation);
+
+    let input: Vec<F> = (1..T as u64).map(F::from).collect();
+    let mut poseidon = Poseidon::new(S::constants(), [F::zero(); T]);
+    assert_eq!(poseidon.hash(&input), F::from_str_vartime(hash).unwrap());
+}
+
+// The concatenated outputs of the squeezes of each IO pattern, with the width 3
+fn check_sponge<F: PrimeField, S: PoseidonSpec<F, 3>>(
+    absorb_mode: AbsorbMode,
+    expected: &[(&str, &[&str])],
+) {
+    for (pattern, output) in expected {
+        let io_pattern: IOPattern = pattern.parse().unwrap();
+        let mut sponge = PoseidonSponge::new(
+            b"test",
+            Poseidon::new(S::constants(), [F::zero(); 3]),
+            S::rate(),
+            absorb_mode,
+            Some(io_pattern.clone()),
+        );
+
+        let mut next_input = 1;
+        let mut squeezed = vec![];
+        for op in io_pattern.0 {
+            match op {
+                SpongeOp::Absorb(length) => {
+                    let input: Vec<F> = (next_input..(next_input + length as u64))
+                        .map(F::from)
+                        .collect();
+                    sponge.absorb(&input).unwrap();
+                    next_input += length as u64;
+                }
+                SpongeOp::Squeeze(length) => squeezed.extend(sponge.squeeze(length).unwrap()),
+            }
+        }
+
+        assert_eq!(sponge.finish(), Ok(()));
+        assert_eq!(squeezed, to_elements::<F>(output), "{}", pattern);
+    }
+}
+
+#[test]
+fn test_hadeshash() {
+    // poseidonperm_x5_254_3
+    check_permutation::<Fr, 3>(
+        Bn254::constants(),
+        &[
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+            "7142104613055408817911962100316808866448378443474503659992478482890339429929",
+            "6549537674122432311777789598043107870002137484850126429160507761192163713804",
+        ],
+    );
+    // poseidonperm_x5_254_5
+    check_permutation::<Fr, 5>(
+        Bn254::constants(),
+        &[
+            "18821383157269793795438455681495246036402687001665670618754263018637548127333",
+            "7817711165059374331357136443537800893307845083525445872661165200086166013245",
+            "16733335996448830230979566039396561240864200624113062088822991822580465420551",
+            "6644334865470350789317807668685953492649391266180911382577082600917830417726",
+            "3372108894677221197912083238087960099443657816445944159266857514496320565191",
+        ],
+    );
+    // poseidonperm_x5_255_3, with other round numbers than Bls12_381
+    check_permutation::<Scalar, 3>(
+        Arc::new(generate_constants(5, 8, 57)),
+        &[
+            "18456658763349757341014058622209659766100673761449600566550821987295786346378",
+            "37068251774887509885063625701815026138353041152735229476479055620962268601796",
+            "26763157702141528937904191329664859174584798817251788852101947537759678822298",
+        ],
+    );
+    // poseidonperm_x5_255_5
+    check_permutation::<Scalar, 5>(
+        Arc::new(generate_constants(5, 8, 60)),
+        &[
+            "19254296030192702347705476604674913000958357963414387321395030558732596555288",
+            "46100521179547351030890356806707858465640857221128207179269773056079077808119",
+            "20243588025220817172052439566138805764005249007732516020412708149385313486286",
+            "35175955397230834946990620339380655400826139806258387820473756013382386627942",
+            "1808162148717216882509606295568402045499399102423428336298026884332797092785",
+        ],
+    );
+}
+
+#[test]
+fn test_parameter_sets() {
+    check_spec::<Fp, K256, 3>(
+        &[
+            "109624812691894397931951736603015001008036094780270444079891614371310953562364",
+            "70219984462654262839888150343700482076858634184829964627926966507115368791429",
+            "96513985414814016594694740464711139322120799866169504905972211398850004704028",
+        ],
+        "16683200200161271854709607420656450536732277545663173621568658205610401188074",
+    );
+    check_spec::<Fp, K256, 5>(
+        &[
+            "68050861458372468956022693203654004779542738455834740108460821065536787503615",
+            "104588872455610642263078267003215380305922337101576967284884806699952481334997",
+            "70654219795159703786098735416022899454096807178954542764507947346555882075597",
+            "102064375086855961153115841583779869934083039023294407755656773652426096442422",
+            "33467954793816898524440011304957379721336927053618602282033477437208429286567",
+        ],
+        "1255014459006249830503918826249361618205369559615085533888605036538563037993",
+    );
+    check_spec::<Fq, Secq256k1, 3>(
+        &[
+            "85337919827686594881793153971614750998257120923619792234661850712698415035904",
+            "1949627791505855889737974633222860787356702689234614627083208486219614161727",
+            "83980808166964270269996275002187748545013526737200225935585304361854967738596",
+        ],
+        "54619696839718512362936560208906379782274656825832257531904619740236370598977",
+    );
+    // hadeshash and circomlibjs
+    check_spec::<Fr, Bn254, 3>(
+        &[
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+            "7142104613055408817911962100316808866448378443474503659992478482890339429929",
+            "6549537674122432311777789598043107870002137484850126429160507761192163713804",
+        ],
+        "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+    );
+    // hadeshash and circomlibjs
+    check_spec::<Fr, Bn254, 5>(
+        &[
+            "18821383157269793795438455681495246036402687001665670618754263018637548127333",
+            "7817711165059374331357136443537800893307845083525445872661165200086166013245",
+            "16733335996448830230979566039396561240864200624113062088822991822580465420551",
+            "6644334865470350789317807668685953492649391266180911382577082600917830417726",
+            "3372108894677221197912083238087960099443657816445944159266857514496320565191",
+        ],
+        "18821383157269793795438455681495246036402687001665670618754263018637548127333",
+    );
+    // The permutation from halo2_gadgets
+    check_spec::<pasta::Fp, Pallas, 3>(
+        &[
+            "19142758212910704988134549186320465225050001548607778483843514680734401733718",
+            "8943457793054409913105520643844025343653237882909500861250463986907015919658",
+            "4653491495579411712133380452970045393126868676144731347343956788496825228765",
+        ],
+        "24123908145095057026791623326467558304806014471451005010637196320467268264780",
+    );
+    // The permutation from halo2_gadgets
+    check_spec::<pasta::Fq, Vesta, 3>(
+        &[
+            "22322561842627156685197453807735251645124552119548776724790988483233524399705",
+            "27090113248495207304570490195654932404673794912237757181609825437423660787185",
+            "17038665073773321051110301570394593864359648438432289432271269645938989965529",
+        ],
+        "10804669183304057164918095060723618749394086025067781816855478524373479273612",
+    );
+    check_spec::<Scalar, Bls12_381, 3>(
+        &[
+            "28821147804331559602169231704816259064962739503761913593647409715501647586810",
+            "30754388626296368040468298266549616538028692312414349123487035395142135348698",
+            "2299091558249312604371495294586620648216137258024480884964079017093457283751",
+        ],
+        "14253191027176020185927177437848259652092360327780294035395052822682034286578",
+    );
+}
+
+#[test]
+fn test_sponge() {
+    check_sponge::<Fp, K256>(
+        AbsorbMode::Overwrite,
+        &[
+            (
+                "A2S1",
+                &[
+                    "110634288230441404541803970888879183149073936326899105378710410876434143133407",
+                ],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "93065703261123409829707064907928535729856254084510761979914379039459205371323",
+                    "13084648785369490422532104326711684655529390365672948717846347270245614176597",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "4839225708401678496948005260345938712112908053344820564100020346818186698694",
+                    "68698567003290827079995089405185581263515070215309128761523042208787273074348",
+                    "56858586426215918523709430850798128579130080766196864495611873306455850197892",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "25419890099850488847980669612415627133303739979749036000942635441563872559932",
+                    "3440109542836670863551832419278251735476295788941363796855399683873222129077",
+                    "76727304904916457857236250828827831894513310966005022209826023976933230320928",
+                    "71049410702243209801313861677586386870225772357731402594731068116368263003703",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "15662129120171420898583692200505409934764227483228970851169482806028813852269",
+                    "26625192333242282783319135336041993145851184936646625999471346165491232601799",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<Fq, Secq256k1>(
+        AbsorbMode::Overwrite,
+        &[
+            (
+                "A2S1",
+                &[
+                    "13673404689377897065453726552502691665748469965944986810534563603527263772578",
+                ],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "42695750649572641778937127616653586832778964412016295877022907811132038295280",
+                    "69022208122005155395384366721504541555424654031500094143741099379630710201000",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "55804804455889758172265411835359464964270799889363176158986836274523914188608",
+                    "32732400875956292508978490808080788320887484860838664106644500487334592674837",
+                    "59372019109806065530580528795809651318771153324977300381830654190160215473178",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "9980879636559517196049248241535385616768220696931482684962458461380179886301",
+                    "22507802776162505268562682581553778724022886809223520140227155915440260199963",
+                    "106431910879928930167089340246342589466953915101936564480719064552070628528992",
+                    "14125016420018102447315566743325840807277425638197354764303585662215299115608",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "92815293191402327460649845140214071080980826055984733530628737977708710555060",
+                    "8507485001664964107697075550848286232603953447620553597995613163039853743764",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<Fr, Bn254>(
+        AbsorbMode::Overwrite,
+        &[
+            (
+                "A2S1",
+                &["13883226652524086883076182546421041659506558096452121661333769057235323865492"],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "20715457328865379260839490009412259997579937876382390569638657167207777661017",
+                    "3052576287504693359962280235073197229978154315559216974577745917556777105824",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "15709352449711789637046422544915428120541751097888340747713732551260679869611",
+                    "21809073059630562287822364867872226694945266215549934808775015358115527359972",
+                    "15386506616997288919699272447420209503858386102557216901729321558873892054650",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "12179080933735675100553417123214171124981996459351576454385427433054886747790",
+                    "16014596987392508694643771111808517653844828658754798059175612977262817030158",
+                    "8979175378698603956293193875198208156145552354786215409076349517201980429350",
+                    "15890631570675848636114701523916115450600392964990031536034799591250628572611",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "12140186563754322124624794994092166763375824607985726912209136858916284966314",
+                    "21016213088070575525306849878771603018445364401654717206953596851515012154995",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<pasta::Fp, Pallas>(
+        AbsorbMode::Overwrite,
+        &[
+            (
+                "A2S1",
+                &["17289047249181788663702073291628185826570959170790473102138987516463492704391"],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "23634290904890618272054671196988169086563137377162215755017253071380547140966",
+                    "6594573845959289266474235844377428961980523729258745612273883662423277286772",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "6727825970273181387842962250874610939377641656932837227660961570613980814692",
+                    "27849393818194789855803000139435815585932206619579525156079996315349455393488",
+                    "10184445449737789362163278573695000951690280550393008655142190718407411200433",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "15022609378072874805245588599167497216142507529988614945869372467066492073814",
+                    "5643167526386992756884575070765772029768314184609617239308111709226268572585",
+                    "24323628126631218302220744996857373281447947730542332173419912868529443815686",
+                    "8622155699037457243278250291972897848279802432092673898158463391673839468870",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "3074246702293097477305575450615190484611365816412383029825931297614635363433",
+                    "14021925315779467102955853755227151404782172578775308613779361345878536367410",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<pasta::Fq, Vesta>(
+        AbsorbMode::Overwrite,
+        &[
+            (
+                "A2S1",
+                &["21379715670925359242692628642336639415381121679496072155997343823652523606250"],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "18689571635252798804903635309477528468862703478450827473304149656125970748070",
+                    "23041276402116641746361967514110992642743840736828260295779674609522992073339",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "25836074135663396817974658129546826613805395666297732386024185891313599514194",
+                    "2893424126823252146769723761687167683229294158986361179436510305822274638341",
+                    "4471925930450490196424010405236633014397272136959846205739800569055109633806",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "2263370533547364342135531107344827674472400956452947388008409034924520890899",
+                    "1504269385789525440014456424015253054750435989007021920204394251360811075827",
+                    "6278054234368031666033441897094114947575395093918270039810320636606480379777",
+                    "18635193574780858573123434797847719260823371637082972233902518184403718809390",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "18341880860933917511294064421940333329910686759826160144179051914873588340020",
+                    "22122262853318375357570684048703851624049642160959196253706252346629295005",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<Scalar, Bls12_381>(
+        AbsorbMode::Overwrite,
+        &[
+            (
+                "A2S1",
+                &["37290477214483783736777656851625405877127972292976600308790442210829130391681"],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "13007287908577686076146571829547187056740896017683163207217564021004029568390",
+                    "44422553201311764741954787846763801262278085295713401753905413978223395511291",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "42800076692132643098994643937557222455591821463447513631322975837153280908733",
+                    "46672535389158125149537173896407309514347368908030253820714324275040570543633",
+                    "16877259129223172453955287332326977343846633479583355686273658284099795440210",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "22538356595067064709159023891329435523296494288733861054782535468943354944552",
+                    "23136751058193444274238185740475902324773762129736096771573844773403029526913",
+                    "23951943129970459835924577943489684241458384735204107144007905313230486747657",
+                    "17286131603910628595420459514801441036117663302883581086432143239402054210097",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "43727090902884914376160638503352067174831899693470647672289933569397523758879",
+                    "31893046161716611372804830314463919068854778442261489375607921716564836236307",
+                ],
+            ),
+        ],
+    );
+}
+
+#[test]
+fn test_sponge_additive() {
+    check_sponge::<Fp, K256>(
+        AbsorbMode::Additive,
+        &[
+            (
+                "A2S1",
+                &[
+                    "68329256057993702304514966357261595030345857163307292789730619301376712419128",
+                ],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "61375958605655975030043047309331800566980547813992243403088886762526198036413",
+                    "14309206633626522458113449854301268488312441006088512401331557168308320072429",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "23100078881444582041099759178510730225957077057328981847990727978604086517075",
+                    "8880252606863226082939616434281677760558459917819962378506321343110852648914",
+                    "26391080832508244080747144573819820222760693541228731036245333360077776962407",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "62976601517142664949274219870149300347621266005457630482255461836423037453168",
+                    "18651628095160866032563011200963423659611556450266200669905873094240841560383",
+                    "103244371399444861293172511302769847275428803931418882074454125637607601612780",
+                    "2404816686589484555140707831967417578660846507324958042439370476351200118913",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "93188738902223185021187069935233743050767239804068672765157616986490085586172",
+                    "37571350575815668576325787623000339107464871543173063349404052780778709658878",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<Fq, Secq256k1>(
+        AbsorbMode::Additive,
+        &[
+            (
+                "A2S1",
+                &["88756815385809159687757247160709426419095273225768203176227312578834583451326"],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "66179934513107198000932959674173176081271091201329494714532799139401592380179",
+                    "52037830862877385690472845205332872523874800110555111668320616221786546833130",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "77276682445081852355482414014057315586069250597673695810951647085633746962678",
+                    "7481706710310786706146473698411093725151291732461112797415912298436352596659",
+                    "38341925988585479494807398289971161600621329481035984790024731295288602103120",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "2642774318215019721998378791573321351200929835218211999532213253046043419660",
+                    "11058296648992171130253001084352466218476971157983858124881503679582720449807",
+                    "66400185116415789670359542180039129689636958155743827691481756518189539502315",
+                    "63235340217536230339332804650629670661734444464853666294449679471741101254197",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "90707606534336662804606771935291357412827379900836439794048884153682765370201",
+                    "29434530838526159483713792939044630220040889796816317283132734899627157798556",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<Fr, Bn254>(
+        AbsorbMode::Additive,
+        &[
+            (
+                "A2S1",
+                &["1239791192168139078724826699716071335343920867083963848578779268820047353676"],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "11400404999905754095414013149615814453494975603363800406155159248272614538364",
+                    "5587298251047685970627028815501320045556896732629764594120354406857363987438",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "13216890561193890977736243527840838136646586428696399298000845150732754581921",
+                    "7401974768131136126264904047278003282298686644356953018152034579753016193154",
+                    "12426274848595558901350642032913777886635531645599371011272636664049684240222",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "17262218059218097585778769223927059941230139448080553096380129885860976894820",
+                    "3434385441654670835420371282434398151277519528318010426738752676507394751258",
+                    "18209822531385347539096025944941411733850492638317835219774873561849164387998",
+                    "20484978730151622457942240160559370393738505915643633587966800674920138641154",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "2118786951411991490929476841953461948965949327980746470348480931642219235202",
+                    "4752575622131810157854586697890918888027179790431418454442644978514441967855",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<pasta::Fp, Pallas>(
+        AbsorbMode::Additive,
+        &[
+            (
+                "A2S1",
+                &["22275790735215755688013308091421230918748442144426089217210470471304106770901"],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "14627088006379074612715953405741652339315348545653075910581580350285754367004",
+                    "21647930830062538783051179848653543163262790102516164170777610240029052584227",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "11526374583250410416241919654733400820062046777588202628086839369034103532696",
+                    "12023975080385989496132871405046681519578157991824724567795014342473696796977",
+                    "771576192202496997790567677518181527948707640724286413798276709290044314745",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "1276009240800859513292004071863143619177860562029401530195681700236447224289",
+                    "25125268450039936576255379721228518457216583971233578256209389142393664014918",
+                    "5843494787556109767382514001794989118491567135145754578947324265861198757267",
+                    "2260233331522829624999518715657296834244206894594217900794540128415185561887",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "20740952881993744538239047704667817366900390998063791302071793853571082665869",
+                    "15479627432039025793365549008282528204640353116868132525874640085933863658882",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<pasta::Fq, Vesta>(
+        AbsorbMode::Additive,
+        &[
+            (
+                "A2S1",
+                &["12356416738502243052612756186731026693028568365884348513050452184852060222955"],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "28021271509377795572638822843902636232163185056650013697037026157876568092978",
+                    "5841002268528961025045269172507173516129594882319914002354204461843899164064",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "23612825004244433877176799578556761151190565340465163590141605312774944232566",
+                    "11416940724865985979200577877921959224311528933247930242253281882827805852739",
+                    "7560919745419897087173950874507443137171830298970893151121569162646969425950",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "12572963363463507268074636480203129572480635387223952214547354972293247316633",
+                    "13134590794471154491535233913914388136889893055502138090649496309321835105135",
+                    "22028385557468042867068945210017218300024479135748303845568025870117209437900",
+                    "12057800798666448927417648014085694274570653220114123423909661354938709676165",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "4393333379359377356276975337663840728060814151570201157167779036908155283813",
+                    "20182535674409996488232431095373485920598981967600315325603635258928021828555",
+                ],
+            ),
+        ],
+    );
+    check_sponge::<Scalar, Bls12_381>(
+        AbsorbMode::Additive,
+        &[
+            (
+                "A2S1",
+                &["6925775671217974847956428524871087839389267002243185863572686016334710821485"],
+            ),
+            (
+                "A1S1A1S1",
+                &[
+                    "12196094964186712378186987656908887023682153214048246813338206671257209196191",
+                    "52306716890826642751283991335020232366503338219010024958262381299477577804845",
+                ],
+            ),
+            (
+                "A3S3",
+                &[
+                    "27915358458642203796653133960624897272140344773754736850861190866764224159224",
+                    "24825061391184362833765593133795866174593668508394936168588095429140919588745",
+                    "18709626593799730781091239280786309620336841111345278081059530395229724748134",
+                ],
+            ),
+            (
+                "A2S1A1S3",
+                &[
+                    "37238312647477821240512385293487127586508664290734440589840084069021316050294",
+                    "32049121335988123734796458335272958743112520565480302488863956932932792687916",
+                    "35406921686854574859746976463196067967397540626371675533877415029530171686540",
+                    "49010434814577071978764966502236529598816435456969442053786211532712656008548",
+                ],
+            ),
+            (
+                "A5A1S2",
+                &[
+                    "19650251486571506643829383599068193240384829116181797423324551528448542599967",
+                    "8432626515533720525800502339904660739061554868553511705025983553453292888979",
+                ],
+            ),
+        ],
+    );
+}
+
+// The tags of SAFE, and of the overwrite mode, for IO patterns and domain
+// separators. Without an IO pattern the additive tag is the prefix of the
+// SHA3-256 hash of the domain separator, so the first two are those of the
+// empty string and of "abc" in the examples of FIPS 202.
+#[test]
+fn test_tag() {
+    let expected = [
+        (
+            "",
+            "",
+            AbsorbMode::Additive,
+            0xa7ffc6f8bf1ed76651c14756a061d662,
+        ),
+        (
+            "",
+            "abc",
+            AbsorbMode::Additive,
+            0x3a985da74fe225b2045c172d6bd390bd,
+        ),
+        (
+            "S1",
+            "",
+            AbsorbMode::Additive,
+            0x7250d6eb424a2ae67b384738f4c918ac,
+        ),
+        (
+            "A2S1",
+            "test",
+            AbsorbMode::Additive,
+            0x19c2216ef3259b17d6f24db4d997e2b6,
+        ),
+        (
+            "A1S1A1S1",
+            "test",
+            AbsorbMode::Additive,
+            0x9c3df912ceb3d95133ac18130da65545,
+        ),
+        // Aggregated as A6S2
+        (
+            "A5A1S2",
+            "test",
+            AbsorbMode::Additive,
+            0xab6047eba28c4de341cbc3a0344df2f4,
+        ),
+        (
+            "A2147483647S2147483647",
+            "test",
+            AbsorbMode::Additive,
+            0x4224bc6238826a2487870edf85ace9e7,
+        ),
+        (
+            "A2S1",
+            "test",
+            AbsorbMode::Overwrite,
+            0x88e5f38c4b50bba4d305b293f90fd2da,
+        ),
+    ];
+
+    for (pattern, domain_separator, absorb_mode, tag) in expected {
+        assert_eq!(
+            pattern
+                .parse::<IOPattern>()
+                .unwrap()
+                .tag(domain_separator.as_bytes(), absorb_mode),
+            Ok(tag),
+            "{}",
+            pattern
+        );
+    }
+}
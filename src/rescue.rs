@@ -0,0 +1,186 @@
+// Rescue-Prime Optimized (https://eprint.iacr.org/2022/1577) over the Goldilocks
+// field with the width 12 and the capacity 4, for 128 bits of security, as used
+// by Miden. Every round applies the MDS matrix, the first round constants, x^7,
+// the MDS matrix again, the second round constants and x^(1/7).
+use crate::goldilocks::Goldilocks;
+use crate::poseidon::{matrix, sbox};
+use crate::spec;
+use ff::Field;
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::Shake256;
+use std::sync::Arc;
+
+pub const WIDTH: usize = 12;
+pub const CAPACITY: usize = 4;
+pub const RATE: usize = WIDTH - CAPACITY;
+pub const NUM_ROUNDS: usize = 7;
+pub const ALPHA: u64 = 7;
+// The inverse of 7 modulo p - 1
+pub const ALPHA_INV: u64 = 10540996611094048183;
+
+// The first row of the circulant MDS matrix
+const MDS_ROW: [u64; WIDTH] = [7, 23, 8, 26, 13, 10, 9, 7, 6, 22, 21, 8];
+
+// The round constants are read from SHAKE256 of this seed, 9 little-endian
+// bytes reduced modulo p for each
+const SEED: &[u8] = b"RPO(18446744069414584321,12,4,128)";
+const BYTES_PER_CONSTANT: usize = 9;
+
+pub struct RescuePrimeConstants {
+    // Added after the x^7 S-boxes of each round
+    pub ark1: [[Goldilocks; WIDTH]; NUM_ROUNDS],
+    // Added after the x^(1/7) S-boxes of each round
+    pub ark2: [[Goldilocks; WIDTH]; NUM_ROUNDS],
+    pub mds_matrix: [[Goldilocks; WIDTH]; WIDTH],
+}
+
+/// Generate the constants of Rescue-Prime Optimized as the reference
+/// implementation does.
+pub fn generate_constants() -> RescuePrimeConstants {
+    let mut shake = Shake256::default();
+    shake.update(SEED);
+    let mut reader = shake.finalize_xof();
+
+    let mut next_constant = || {
+        let mut bytes = [0u8; BYTES_PER_CONSTANT];
+        reader.read(&mut bytes);
+        bytes.iter().rev().fold(Goldilocks::zero(), |acc, byte| {
+            acc * Goldilocks::from(256) + Goldilocks::from(*byte as u64)
+        })
+    };
+
+    let mut ark1 = [[Goldilocks::zero(); WIDTH]; NUM_ROUNDS];
+    let mut ark2 = [[Goldilocks::zero(); WIDTH]; NUM_ROUNDS];
+    for (round_keys1, round_keys2) in ark1.iter_mut().zip(ark2.iter_mut()) {
+        round_keys1.fill_with(&mut next_constant);
+        round_keys2.fill_with(&mut next_constant);
+    }
+
+    // Each row is the previous one rotated to the right
+    let mds_matrix = std::array::from_fn(|i| {
+        std::array::from_fn(|j| Goldilocks::from(MDS_ROW[(j + WIDTH - i) % WIDTH]))
+    });
+
+    RescuePrimeConstants {
+        ark1,
+        ark2,
+        mds_matrix,
+    }
+}
+
+/// The constants of Rescue-Prime Optimized, shared by every caller.
+pub fn constants() -> Arc<RescuePrimeConstants> {
+    spec::cached::<RescuePrimeConstants, _>(generate_constants)
+}
+
+pub struct RescuePrime {
+    pub state: [Goldilocks; WIDTH],
+    pub constants: Arc<RescuePrimeConstants>,
+}
+
+impl RescuePrime {
+    pub fn new(constants: Arc<RescuePrimeConstants>, state: [Goldilocks; WIDTH]) -> Self {
+        Self { state, constants }
+    }
+
+    /// Permute the state, in a time that doesn't depend on the state like
+    /// [`Poseidon::permute`]. The inverse S-box is a fixed square-and-multiply
+    /// over the bits of 1/7.
+    ///
+    /// [`Poseidon::permute`]: crate::poseidon::Poseidon::permute
+    pub fn permute(&mut self) {
+        for round in 0..NUM_ROUNDS {
+            self.round(round);
+        }
+    }
+
+    fn round(&mut self, round: usize) {
+        let (ark1, ark2) = (self.constants.ark1[round], self.constants.ark2[round]);
+
+        self.matrix_mul();
+        self.add_constants(&ark1);
+        for x in self.state.iter_mut() {
+            *x = sbox::pow(*x, ALPHA);
+        }
+
+        self.matrix_mul();
+        self.add_constants(&ark2);
+        for x in self.state.iter_mut() {
+            *x = sbox::pow(*x, ALPHA_INV);
+        }
+    }
+
+    fn add_constants(&mut self, round_keys: &[Goldilocks; WIDTH]) {
+        for (x, c) in self.state.iter_mut().zip(round_keys.iter()) {
+            *x += c;
+        }
+    }
+
+    // MDS matrix multiplication
+    fn matrix_mul(&mut self) {
+        self.state = matrix::mul_array(&self.constants.mds_matrix, &self.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constants() {
+        let constants = generate_constants();
+
+        // The first round constants of Miden's ARK1 and ARK2
+        assert_eq!(constants.ark1[0][0].to_canonical_u64(), 5789762306288267392);
+        assert_eq!(constants.ark2[0][0].to_canonical_u64(), 6077062762357204287);
+
+        assert_eq!(
+            sbox::pow(sbox::pow(Goldilocks::from(1234567), ALPHA), ALPHA_INV),
+            Goldilocks::from(1234567)
+        );
+    }
+
+    // Rpo256::hash_elements of miden-crypto before its padding rule changed: the
+    // first capacity element is the input length modulo the rate, and the last
+    // block is padded with zeros
+    fn hash_elements(elements: &[u64]) -> [u64; 4] {
+        let mut rescue = RescuePrime::new(constants(), [Goldilocks::zero(); WIDTH]);
+        rescue.state[0] = Goldilocks::from((elements.len() % RATE) as u64);
+
+        for block in elements.chunks(RATE) {
+            for (i, x) in rescue.state[CAPACITY..].iter_mut().enumerate() {
+                *x = block
+                    .get(i)
+                    .map_or(Goldilocks::zero(), |x| Goldilocks::from(*x));
+            }
+            rescue.permute();
+        }
+
+        std::array::from_fn(|i| rescue.state[CAPACITY + i].to_canonical_u64())
+    }
+
+    #[test]
+    fn test_permutation() {
+        // EXPECTED[0] and EXPECTED[1] of src/hash/rescue/rpo/tests.rs in
+        // miden-crypto (https://github.com/0xPolygonMiden/crypto), the digests of
+        // [0] and [0, 1]
+        assert_eq!(
+            hash_elements(&[0]),
+            [
+                18126731724905382595,
+                7388557040857728717,
+                14290750514634285295,
+                7852282086160480146,
+            ]
+        );
+        assert_eq!(
+            hash_elements(&[0, 1]),
+            [
+                10139303045932500183,
+                2293916558361785533,
+                15496361415980502047,
+                17904948502382283940,
+            ]
+        );
+    }
+}
@@ -0,0 +1,403 @@
+// Parameter sets of the sponge and the transcript. Each one is a marker type
+// implementing `PoseidonSpec` for its field, so that using a parameter set with
+// another field doesn't compile.
+use crate::poseidon::grain::generate_round_constants_and_mds;
+use crate::poseidon::{
+    bn254_consts, k256_consts, log2_modulus, pasta_consts, round_numbers, HashMode,
+    PoseidonConstants,
+};
+use crate::poseidon2::{self, Poseidon2Constants, Poseidon2Error};
+use ff::PrimeField;
+use halo2curves::{bn256, pasta, secp256k1};
+use std::any::{Any, TypeId};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A Poseidon parameter set over the field `F` with the width `T`.
+pub trait PoseidonSpec<F: PrimeField, const T: usize>: 'static {
+    fn num_full_rounds() -> usize;
+
+    fn num_partial_rounds() -> usize;
+
+    /// The S-box exponent.
+    fn alpha() -> u64;
+
+    /// The round constants, `T` per round, and the MDS matrix. Only called once
+    /// per process, by [`PoseidonSpec::constants`].
+    fn generate_constants() -> (Vec<F>, [[F; T]; T]);
+
+    /// The number of elements absorbed or squeezed per permutation. The rest of
+    /// the state is the capacity.
+    fn rate() -> usize {
+        T - 1
+    }
+
+    /// The input layout and output of [`Poseidon::hash`].
+    ///
+    /// [`Poseidon::hash`]: crate::poseidon::Poseidon::hash
+    fn hash_mode() -> HashMode {
+        HashMode::DomainTag
+    }
+
+    /// The Poseidon2 constants, with the same numbers of rounds and S-box by
+    /// default. Only called by [`PoseidonSpec::poseidon2_constants`], until it
+    /// succeeds. Poseidon2 isn't defined for every width.
+    fn generate_poseidon2_constants() -> Result<Poseidon2Constants<F, T>, Poseidon2Error> {
+        poseidon2::generate_constants(
+            Self::alpha(),
+            Self::num_full_rounds(),
+            Self::num_partial_rounds(),
+        )
+    }
+
+    /// The Poseidon constants, shared by every caller.
+    fn constants() -> Arc<PoseidonConstants<F, T>> {
+        cached::<Self, _>(|| {
+            let (round_constants, mds_matrix) = Self::generate_constants();
+
+            PoseidonConstants::new(
+                round_constants,
+                mds_matrix,
+                Self::alpha(),
+                Self::num_full_rounds(),
+                Self::num_partial_rounds(),
+            )
+            .with_hash_mode(Self::hash_mode())
+        })
+    }
+
+    /// The Poseidon2 constants, shared by every caller.
+    fn poseidon2_constants() -> Result<Arc<Poseidon2Constants<F, T>>, Poseidon2Error> {
+        try_cached::<Self, _, _>(Self::generate_poseidon2_constants)
+    }
+}
+
+// Constants of each parameter set, field and width, keyed by the types of the
+// parameter set and of the constants
+type ConstantsCache = BTreeMap<TypeId, Arc<dyn Any + Send + Sync>>;
+
+static CONSTANTS: Mutex<ConstantsCache> = Mutex::new(BTreeMap::new());
+
+// Get the constants from the process-wide cache, building them on first use.
+// The lock isn't held while building, so that `build` can get the constants of
+// another parameter set, and so that parameter sets are built concurrently. If
+// two threads build the same constants, the first one inserted is kept.
+pub(crate) fn cached<S: ?Sized + 'static, C: Any + Send + Sync>(
+    build: impl FnOnce() -> C,
+) -> Arc<C> {
+    match try_cached::<S, C, Infallible>(|| Ok(build())) {
+        Ok(constants) => constants,
+        Err(never) => match never {},
+    }
+}
+
+// Same as `cached` for constants that may fail to build. Failures aren't cached.
+pub(crate) fn try_cached<S: ?Sized + 'static, C: Any + Send + Sync, E>(
+    build: impl FnOnce() -> Result<C, E>,
+) -> Result<Arc<C>, E> {
+    let key = TypeId::of::<(&'static S, C)>();
+
+    // A panic while building leaves the cache untouched
+    let lookup = || CONSTANTS.lock().unwrap_or_else(PoisonError::into_inner);
+
+    if let Some(constants) = lookup().get(&key) {
+        return Ok(constants.clone().downcast().unwrap());
+    }
+
+    let built: Arc<dyn Any + Send + Sync> = Arc::new(build()?);
+    let constants = lookup().entry(key).or_insert(built).clone();
+
+    Ok(constants.downcast().unwrap())
+}
+
+// All the parameter sets of this crate use x^5
+const ALPHA: u64 = 5;
+
+// Security level in bits of the round numbers computed by this crate, for the
+// Poseidon widths without fixed parameters and for every Poseidon2 instance
+pub(crate) const SECURITY_LEVEL: u32 = 128;
+
+// Round numbers of the generated parameter sets, which support the widths 2 to 16
+fn secure_round_numbers<F: PrimeField, const T: usize>() -> (usize, usize) {
+    assert!((2..=16).contains(&T), "the width must be between 2 and 16");
+
+    round_numbers(log2_modulus::<F>(), T, ALPHA, SECURITY_LEVEL)
+}
+
+// The parsed constants of k256_consts for the width 3
+fn k256_round_numbers<const T: usize>() -> (usize, usize) {
+    if T == 3 {
+        (
+            k256_consts::NUM_FULL_ROUNDS,
+            k256_consts::NUM_PARTIAL_ROUNDS,
+        )
+    } else {
+        secure_round_numbers::<secp256k1::Fp, T>()
+    }
+}
+
+/// The base field of secp256k1.
+pub struct K256;
+
+impl<const T: usize> PoseidonSpec<secp256k1::Fp, T> for K256 {
+    fn num_full_rounds() -> usize {
+        k256_round_numbers::<T>().0
+    }
+
+    fn num_partial_rounds() -> usize {
+        k256_round_numbers::<T>().1
+    }
+
+    fn alpha() -> u64 {
+        ALPHA
+    }
+
+    fn generate_constants() -> (Vec<secp256k1::Fp>, [[secp256k1::Fp; T]; T]) {
+        if T != 3 {
+            let (num_full_rounds, num_partial_rounds) = k256_round_numbers::<T>();
+            return generate_round_constants_and_mds(num_full_rounds, num_partial_rounds);
+        }
+
+        let round_constants = k256_consts::ROUND_CONSTANTS
+            .iter()
+            .map(|x| secp256k1::Fp::from_str_vartime(x).unwrap())
+            .collect();
+
+        let mds_matrix = std::array::from_fn(|i| {
+            std::array::from_fn(|j| {
+                secp256k1::Fp::from_str_vartime(k256_consts::MDS_MATRIX[i][j]).unwrap()
+            })
+        });
+
+        (round_constants, mds_matrix)
+    }
+}
+
+/// The base field of secq256k1, which is the scalar field of secp256k1.
+pub struct Secq256k1;
+
+impl<const T: usize> PoseidonSpec<secp256k1::Fq, T> for Secq256k1 {
+    fn num_full_rounds() -> usize {
+        secure_round_numbers::<secp256k1::Fq, T>().0
+    }
+
+    fn num_partial_rounds() -> usize {
+        secure_round_numbers::<secp256k1::Fq, T>().1
+    }
+
+    fn alpha() -> u64 {
+        ALPHA
+    }
+
+    fn generate_constants() -> (Vec<secp256k1::Fq>, [[secp256k1::Fq; T]; T]) {
+        let (num_full_rounds, num_partial_rounds) = secure_round_numbers::<secp256k1::Fq, T>();
+
+        generate_round_constants_and_mds(num_full_rounds, num_partial_rounds)
+    }
+}
+
+/// The scalar field of BN254, compatible with circomlib for the widths 2 to 17.
+pub struct Bn254;
+
+impl<const T: usize> PoseidonSpec<bn256::Fr, T> for Bn254 {
+    fn num_full_rounds() -> usize {
+        bn254_consts::NUM_FULL_ROUNDS
+    }
+
+    fn num_partial_rounds() -> usize {
+        assert!((2..=17).contains(&T), "the width must be between 2 and 17");
+
+        bn254_consts::NUM_PARTIAL_ROUNDS_BY_WIDTH[T - 2]
+    }
+
+    fn alpha() -> u64 {
+        ALPHA
+    }
+
+    fn generate_constants() -> (Vec<bn256::Fr>, [[bn256::Fr; T]; T]) {
+        generate_round_constants_and_mds(
+            bn254_consts::NUM_FULL_ROUNDS,
+            <Self as PoseidonSpec<bn256::Fr, T>>::num_partial_rounds(),
+        )
+    }
+
+    fn hash_mode() -> HashMode {
+        HashMode::Circom
+    }
+
+    fn generate_poseidon2_constants() -> Result<Poseidon2Constants<bn256::Fr, T>, Poseidon2Error> {
+        // circomlib's rounding of the number of partial rounds is specific to Poseidon
+        poseidon2::generate_secure_constants(ALPHA, SECURITY_LEVEL)
+    }
+}
+
+// P128Pow5T3 for the width 3
+fn pasta_round_numbers<F: PrimeField, const T: usize>() -> (usize, usize) {
+    if T == 3 {
+        (
+            pasta_consts::NUM_FULL_ROUNDS,
+            pasta_consts::NUM_PARTIAL_ROUNDS,
+        )
+    } else {
+        secure_round_numbers::<F, T>()
+    }
+}
+
+/// The base field of Pallas, compatible with halo2_gadgets for the width 3.
+pub struct Pallas;
+
+impl<const T: usize> PoseidonSpec<pasta::Fp, T> for Pallas {
+    fn num_full_rounds() -> usize {
+        pasta_round_numbers::<pasta::Fp, T>().0
+    }
+
+    fn num_partial_rounds() -> usize {
+        pasta_round_numbers::<pasta::Fp, T>().1
+    }
+
+    fn alpha() -> u64 {
+        ALPHA
+    }
+
+    fn generate_constants() -> (Vec<pasta::Fp>, [[pasta::Fp; T]; T]) {
+        let (num_full_rounds, num_partial_rounds) = pasta_round_numbers::<pasta::Fp, T>();
+
+        generate_round_constants_and_mds(num_full_rounds, num_partial_rounds)
+    }
+
+    fn hash_mode() -> HashMode {
+        HashMode::ConstantLength
+    }
+}
+
+/// The base field of Vesta, compatible with halo2_gadgets for the width 3.
+pub struct Vesta;
+
+impl<const T: usize> PoseidonSpec<pasta::Fq, T> for Vesta {
+    fn num_full_rounds() -> usize {
+        pasta_round_numbers::<pasta::Fq, T>().0
+    }
+
+    fn num_partial_rounds() -> usize {
+        pasta_round_numbers::<pasta::Fq, T>().1
+    }
+
+    fn alpha() -> u64 {
+        ALPHA
+    }
+
+    fn generate_constants() -> (Vec<pasta::Fq>, [[pasta::Fq; T]; T]) {
+        let (num_full_rounds, num_partial_rounds) = pasta_round_numbers::<pasta::Fq, T>();
+
+        generate_round_constants_and_mds(num_full_rounds, num_partial_rounds)
+    }
+
+    fn hash_mode() -> HashMode {
+        HashMode::ConstantLength
+    }
+}
+
+/// The scalar field of BLS12-381.
+pub struct Bls12_381;
+
+impl<const T: usize> PoseidonSpec<bls12_381::Scalar, T> for Bls12_381 {
+    fn num_full_rounds() -> usize {
+        secure_round_numbers::<bls12_381::Scalar, T>().0
+    }
+
+    fn num_partial_rounds() -> usize {
+        secure_round_numbers::<bls12_381::Scalar, T>().1
+    }
+
+    fn alpha() -> u64 {
+        ALPHA
+    }
+
+    fn generate_constants() -> (Vec<bls12_381::Scalar>, [[bls12_381::Scalar; T]; T]) {
+        let (num_full_rounds, num_partial_rounds) = secure_round_numbers::<bls12_381::Scalar, T>();
+
+        generate_round_constants_and_mds(num_full_rounds, num_partial_rounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sponge::{AbsorbMode, PoseidonSponge, SpongePermutation};
+
+    // A parameter set defined outside of this crate, with the constants of K256
+    // and a rate of one
+    struct K256RateOne;
+
+    impl PoseidonSpec<secp256k1::Fp, 3> for K256RateOne {
+        fn num_full_rounds() -> usize {
+            k256_consts::NUM_FULL_ROUNDS
+        }
+
+        fn num_partial_rounds() -> usize {
+            k256_consts::NUM_PARTIAL_ROUNDS
+        }
+
+        fn alpha() -> u64 {
+            ALPHA
+        }
+
+        fn generate_constants() -> (Vec<secp256k1::Fp>, [[secp256k1::Fp; 3]; 3]) {
+            <K256 as PoseidonSpec<secp256k1::Fp, 3>>::generate_constants()
+        }
+
+        fn rate() -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn test_custom_spec() {
+        // The constants are cached separately for each parameter set
+        let constants: Arc<PoseidonConstants<secp256k1::Fp, 3>> = K256RateOne::constants();
+        let k256_constants = K256::constants();
+        assert!(!Arc::ptr_eq(&constants, &k256_constants));
+        assert_eq!(constants.round_keys, k256_constants.round_keys);
+        assert!(Arc::ptr_eq(&constants, &K256RateOne::constants()));
+
+        let sponge = PoseidonSponge::<secp256k1::Fp>::construct(
+            b"test",
+            K256RateOne,
+            SpongePermutation::Poseidon,
+            AbsorbMode::Overwrite,
+            None,
+        );
+        assert_eq!((sponge.rate, sponge.capacity), (1, 2));
+    }
+
+    // A parameter set that reuses the cached constants of K256
+    struct K256Reused;
+
+    impl PoseidonSpec<secp256k1::Fp, 3> for K256Reused {
+        fn num_full_rounds() -> usize {
+            k256_consts::NUM_FULL_ROUNDS
+        }
+
+        fn num_partial_rounds() -> usize {
+            k256_consts::NUM_PARTIAL_ROUNDS
+        }
+
+        fn alpha() -> u64 {
+            ALPHA
+        }
+
+        fn generate_constants() -> (Vec<secp256k1::Fp>, [[secp256k1::Fp; 3]; 3]) {
+            let constants: Arc<PoseidonConstants<secp256k1::Fp, 3>> = K256::constants();
+            (constants.round_keys.clone(), constants.mds_matrix)
+        }
+    }
+
+    #[test]
+    fn test_nested_spec() {
+        // Building the constants gets those of another parameter set from the
+        // cache, which must not deadlock
+        let constants = K256Reused::constants();
+        assert_eq!(constants.round_keys, K256::constants().round_keys);
+    }
+}
@@ -1,162 +1,455 @@
-use crate::poseidon::k256_consts;
-use crate::poseidon::{Poseidon, PoseidonConstants};
+use crate::permutation::{BoxedPermutation, Permutation};
+use crate::poseidon::Poseidon;
+use crate::poseidon2::{Poseidon2, Poseidon2Error};
+use crate::spec::PoseidonSpec;
 use ff::PrimeField;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use sha3::{Digest, Sha3_256};
+use std::fmt;
+use std::marker::PhantomData;
 use std::result::Result;
+use std::str::FromStr;
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SpongeOp {
     Absorb(usize),
     Squeeze(usize),
 }
 
-#[derive(Clone)]
+// The longest operation, or run of consecutive operations of the same kind,
+// that a 32-bit word of the tag encodes
+const MAX_OP_LENGTH: usize = 0x7fff_ffff;
+
+/// The operations of a sponge, in order. Its text form is the concatenation of
+/// the operations, such as "A2S1A1S3" for Absorb(2), Squeeze(1), Absorb(1) and
+/// Squeeze(3), which is also its serde representation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct IOPattern(pub Vec<SpongeOp>);
 
-// Implements SAFE (Sponge API for Field Elements): https://hackmd.io/bHgsH6mMStCVibM_wYvb2w
-pub struct PoseidonSponge<F: PrimeField> {
+impl IOPattern {
+    pub fn new() -> Self {
+        Self(vec![])
+    }
+
+    pub fn absorb(mut self, length: usize) -> Self {
+        self.0.push(SpongeOp::Absorb(length));
+        self
+    }
+
+    pub fn squeeze(mut self, length: usize) -> Self {
+        self.0.push(SpongeOp::Squeeze(length));
+        self
+    }
+
+    /// The pattern with the consecutive operations of the same kind merged, as
+    /// they are in the tag.
+    pub fn aggregate(&self) -> Self {
+        let mut aggregated: Vec<SpongeOp> = vec![];
+        for op in &self.0 {
+            match (aggregated.last_mut(), *op) {
+                (Some(SpongeOp::Absorb(n)), SpongeOp::Absorb(m))
+                | (Some(SpongeOp::Squeeze(n)), SpongeOp::Squeeze(m)) => *n = n.saturating_add(m),
+                _ => aggregated.push(*op),
+            }
+        }
+
+        Self(aggregated)
+    }
+
+    /// The 128-bit tag of the pattern and the domain separator, as defined in
+    /// section 2.3 of SAFE for the additive mode: the first 16 bytes of the
+    /// SHA3-256 hash of the encoded pattern and the domain separator, read as
+    /// a big-endian integer.
+    ///
+    /// This doesn't interoperate with neptune, on purpose: neptune hashes the
+    /// 32-bit words of the pattern and a 32-bit domain separator with a
+    /// polynomial hash modulo 2^128 instead of SHA3-256, so its tags, and the
+    /// outputs of its sponge, differ from these.
+    pub fn tag(
+        &self,
+        domain_separator: &[u8],
+        absorb_mode: AbsorbMode,
+    ) -> Result<u128, SpongeError> {
+        if self
+            .0
+            .iter()
+            .any(|op| matches!(op, SpongeOp::Absorb(0) | SpongeOp::Squeeze(0)))
+        {
+            return Err(SpongeError::EmptyOp);
+        }
+
+        // step 1 and 2: Aggregate the operations and encode them, with the top
+        // bit set for the absorptions, which gives the same words as
+        // aggregating them once encoded. The overwrite mode is committed by a
+        // leading zero word, which no IO pattern encodes, so that the additive
+        // mode keeps the tag of SAFE.
+        let mut io_bytes = vec![];
+        if absorb_mode == AbsorbMode::Overwrite {
+            io_bytes.extend_from_slice(&[0u8; 4]);
+        }
+        for op in self.aggregate().0 {
+            let word = match op {
+                SpongeOp::Absorb(n) if n <= MAX_OP_LENGTH => 0x8000_0000 | n as u32,
+                SpongeOp::Squeeze(n) if n <= MAX_OP_LENGTH => n as u32,
+                SpongeOp::Absorb(length) | SpongeOp::Squeeze(length) => {
+                    return Err(SpongeError::OversizedOp { length })
+                }
+            };
+            io_bytes.extend_from_slice(&word.to_be_bytes());
+        }
+
+        // step 3: Serialize
+        io_bytes.extend_from_slice(domain_separator);
+
+        // step 4: Hash, and keep the first 128 bits
+        let result = Sha3_256::digest(&io_bytes);
+        Ok(u128::from_be_bytes(result[..16].try_into().unwrap()))
+    }
+}
+
+impl fmt::Display for IOPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for op in &self.0 {
+            match op {
+                SpongeOp::Absorb(length) => write!(f, "A{}", length)?,
+                SpongeOp::Squeeze(length) => write!(f, "S{}", length)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for IOPattern {
+    type Err = SpongeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let syntax_error = || SpongeError::Syntax(s.to_string());
+
+        let mut ops = vec![];
+        let mut rest = s;
+        while let Some(op) = rest.chars().next() {
+            let op: fn(usize) -> SpongeOp = match op {
+                'A' => SpongeOp::Absorb,
+                'S' => SpongeOp::Squeeze,
+                _ => return Err(syntax_error()),
+            };
+
+            // The letter is followed by the decimal length
+            let end = rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(rest.len(), |end| end + 1);
+            let length = rest[1..end].parse().map_err(|_| syntax_error())?;
+
+            ops.push(op(length));
+            rest = &rest[end..];
+        }
+
+        Ok(Self(ops))
+    }
+}
+
+impl Serialize for IOPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for IOPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// How the absorbed elements enter the rate part of the state. The mode is
+/// committed in the tag, so that sponges of different modes never share a
+/// state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbsorbMode {
+    /// Replace the elements of the rate. This isn't defined by SAFE.
+    Overwrite,
+    /// Add to the elements of the rate, as described in SAFE.
+    Additive,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpongeError {
+    // The call doesn't match the next operation of the IO pattern, which is None
+    // after the last one
+    UnexpectedOp {
+        expected: Option<SpongeOp>,
+        found: SpongeOp,
+    },
+    // The sponge was finished before the end of the IO pattern
+    IncompletePattern {
+        remaining: usize,
+    },
+    // An earlier call violated the IO pattern, and the state was erased
+    Poisoned,
+    // The rate must leave at least one element of capacity
+    InvalidRate {
+        rate: usize,
+        width: usize,
+    },
+    // The IO pattern has an operation of length zero
+    EmptyOp,
+    // An operation, or a run of operations of the same kind, doesn't fit in the
+    // 31 bits of its tag word
+    OversizedOp {
+        length: usize,
+    },
+    // The capacity has fewer elements than the tag needs
+    InsufficientCapacity {
+        capacity: usize,
+        required: usize,
+    },
+    // The text form of an IO pattern is malformed
+    Syntax(String),
+    // The parameter set has no Poseidon2 instance
+    Poseidon2(Poseidon2Error),
+}
+
+impl fmt::Display for SpongeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpongeError::UnexpectedOp {
+                expected: Some(expected),
+                found,
+            } => write!(
+                f,
+                "the IO pattern expects {:?} instead of {:?}",
+                expected, found
+            ),
+            SpongeError::UnexpectedOp {
+                expected: None,
+                found,
+            } => write!(f, "{:?} is past the end of the IO pattern", found),
+            SpongeError::IncompletePattern { remaining } => write!(
+                f,
+                "the sponge was finished with {} operations of the IO pattern left",
+                remaining
+            ),
+            SpongeError::Poisoned => {
+                write!(f, "the sponge is poisoned by an earlier violation")
+            }
+            SpongeError::InvalidRate { rate, width } => write!(
+                f,
+                "the rate {} must be between 1 and the width {} minus one",
+                rate, width
+            ),
+            SpongeError::EmptyOp => {
+                write!(f, "the IO pattern has an operation of length zero")
+            }
+            SpongeError::OversizedOp { length } => write!(
+                f,
+                "the length {} of an IO pattern operation exceeds 2^31 - 1",
+                length
+            ),
+            SpongeError::InsufficientCapacity { capacity, required } => write!(
+                f,
+                "the tag needs {} capacity elements, but the capacity is {}",
+                required, capacity
+            ),
+            SpongeError::Syntax(pattern) => write!(f, "malformed IO pattern {:?}", pattern),
+            SpongeError::Poseidon2(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SpongeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SpongeError::Poseidon2(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<Poseidon2Error> for SpongeError {
+    fn from(err: Poseidon2Error) -> Self {
+        SpongeError::Poseidon2(err)
+    }
+}
+
+// Follows SAFE (Sponge API for Field Elements): https://hackmd.io/bHgsH6mMStCVibM_wYvb2w
+// The outputs haven't been checked against another SAFE implementation.
+// The state is the capacity followed by the rate, and the tag is in the first
+// elements of the capacity.
+pub struct PoseidonSponge<
+    F: PrimeField,
+    const T: usize = 3,
+    P: Permutation<F, T> = BoxedPermutation<F, T>,
+> {
     pub absorb_pos: usize,
     pub squeeze_pos: usize,
     pub io_count: usize,
     pub io_pattern: Option<IOPattern>,
     pub rate: usize,
     pub capacity: usize,
-    poseidon: Poseidon<F>,
+    pub absorb_mode: AbsorbMode,
+    poisoned: bool,
+    permutation: P,
+    _field: PhantomData<F>,
 }
 
-pub enum SpongeCurve {
-    K256,
+pub enum SpongePermutation {
+    Poseidon,
+    Poseidon2,
 }
 
-impl<F: PrimeField<Repr = [u8; 32]>> PoseidonSponge<F> {
-    pub fn construct(
+impl<F: PrimeField, const T: usize> PoseidonSponge<F, T> {
+    // The rate is given by the parameter set, and the capacity is the rest of the
+    // state. Panics if the sponge can't be built, see `try_construct`.
+    pub fn construct<S: PoseidonSpec<F, T>>(
         domain_separator: &[u8],
-        curve: SpongeCurve,
+        spec: S,
+        permutation: SpongePermutation,
+        absorb_mode: AbsorbMode,
         io_pattern: Option<IOPattern>,
     ) -> Self {
-        // Parse the constants from string
-        let constants = match curve {
-            SpongeCurve::K256 => {
-                let round_constants: Vec<F> = k256_consts::ROUND_CONSTANTS
-                    .iter()
-                    .map(|x| F::from_str_vartime(x).unwrap())
-                    .collect();
-
-                let mds_matrix: Vec<Vec<F>> = k256_consts::MDS_MATRIX
-                    .iter()
-                    .map(|x| {
-                        x.iter()
-                            .map(|y| F::from_str_vartime(y).unwrap())
-                            .collect::<Vec<F>>()
-                    })
-                    .collect();
-
-                PoseidonConstants::new(
-                    round_constants,
-                    mds_matrix,
-                    k256_consts::NUM_FULL_ROUNDS,
-                    k256_consts::NUM_PARTIAL_ROUNDS,
-                )
+        Self::try_construct(domain_separator, spec, permutation, absorb_mode, io_pattern)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // Fails if the rate or the IO pattern is invalid, or if the parameter set has
+    // no Poseidon2 instance
+    pub fn try_construct<S: PoseidonSpec<F, T>>(
+        domain_separator: &[u8],
+        _spec: S,
+        permutation: SpongePermutation,
+        absorb_mode: AbsorbMode,
+        io_pattern: Option<IOPattern>,
+    ) -> Result<Self, SpongeError> {
+        let state = [F::zero(); T];
+        let permutation: BoxedPermutation<F, T> = match permutation {
+            SpongePermutation::Poseidon => Box::new(Poseidon::new(S::constants(), state)),
+            SpongePermutation::Poseidon2 => {
+                Box::new(Poseidon2::new(S::poseidon2_constants()?, state))
             }
         };
 
-        let tag = Self::compute_tag(domain_separator, &io_pattern);
-
-        let state = vec![tag, F::zero(), F::zero()];
-
-        let poseidon = Poseidon::new(constants, state);
-
-        Self {
-            absorb_pos: 0,
-            squeeze_pos: 0,
-            io_count: 0,
+        Self::try_new(
+            domain_separator,
+            permutation,
+            S::rate(),
+            absorb_mode,
             io_pattern,
-            rate: 2,
-            capacity: 1,
-            poseidon,
-        }
-    }
-
-    // Compute tag as described in section 2.3 of the SAFE documentation
-    fn compute_tag(domain_separator: &[u8], io_pattern: &Option<IOPattern>) -> F {
-        // step 1: Encode
-        let io_words = match &io_pattern {
-            Some(io) => {
-                io.0.iter()
-                    .map(|io_i| match io_i {
-                        SpongeOp::Absorb(n) => (n + 0x80000000) as u32,
-                        SpongeOp::Squeeze(n) => (*n) as u32,
-                    })
-                    .collect()
-            }
-            None => {
-                vec![]
-            }
-        };
+        )
+    }
+}
 
-        // step 2: Aggregate
-        let mut io_words_aggregated = vec![];
-        for io_word in io_words {
-            if io_words_aggregated.len() == 0 {
-                io_words_aggregated.push(io_word);
-            } else {
-                let i = io_words_aggregated.len() - 1;
-                if io_words_aggregated[i] > 0x80000000 && io_word > 0x80000000 {
-                    io_words_aggregated[i] += io_word - 0x80000000;
-                } else if io_words_aggregated[i] < 0x80000000 && io_word < 0x80000000 {
-                    io_words_aggregated[i] += io_word;
-                } else {
-                    io_words_aggregated.push(io_word);
-                }
-            }
+impl<F: PrimeField, const T: usize, P: Permutation<F, T>> PoseidonSponge<F, T, P> {
+    // A sponge over any permutation of the state, whose capacity is the rest of
+    // the state before the rate. It's only constant time if the permutation is,
+    // see `Permutation`. Panics if the rate or the IO pattern is invalid.
+    pub fn new(
+        domain_separator: &[u8],
+        permutation: P,
+        rate: usize,
+        absorb_mode: AbsorbMode,
+        io_pattern: Option<IOPattern>,
+    ) -> Self {
+        Self::try_new(domain_separator, permutation, rate, absorb_mode, io_pattern)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_new(
+        domain_separator: &[u8],
+        mut permutation: P,
+        rate: usize,
+        absorb_mode: AbsorbMode,
+        io_pattern: Option<IOPattern>,
+    ) -> Result<Self, SpongeError> {
+        if !(1..T).contains(&rate) {
+            return Err(SpongeError::InvalidRate { rate, width: T });
         }
 
-        // step 3: Serialize
-        let mut io_bytes = vec![];
-        for io_word in io_words_aggregated {
-            io_word.to_be_bytes().iter().for_each(|x| io_bytes.push(*x));
+        let capacity = T - rate;
+        // Without an IO pattern, the tag only commits to the domain separator
+        let no_pattern = IOPattern::new();
+        let tag = io_pattern
+            .as_ref()
+            .unwrap_or(&no_pattern)
+            .tag(domain_separator, absorb_mode)?;
+        let tag = Self::tag_elements(tag);
+        if tag.len() > capacity {
+            return Err(SpongeError::InsufficientCapacity {
+                capacity,
+                required: tag.len(),
+            });
         }
-        io_bytes.extend_from_slice(domain_separator);
 
-        // step 4: Hash
-        let mut hasher = Sha3_256::new();
-        hasher.update(&io_bytes.as_slice());
-        let result = hasher.finalize();
+        let state = permutation.state_mut();
+        *state = [F::zero(); T];
+        state[..tag.len()].copy_from_slice(&tag);
 
-        // Truncate the first 128 bits of the hash to compute the tag
-        let mut tag = Vec::with_capacity(32);
-        tag.extend_from_slice(&[0u8; 16]);
-        tag.extend_from_slice(&result[0..16]);
+        Ok(Self {
+            absorb_pos: 0,
+            squeeze_pos: 0,
+            io_count: 0,
+            io_pattern,
+            rate,
+            capacity,
+            absorb_mode,
+            poisoned: false,
+            permutation,
+            _field: PhantomData,
+        })
+    }
 
-        F::from_repr(tag.as_slice().try_into().unwrap()).unwrap()
+    // The tag as little-endian limbs of F::CAPACITY bits, so that each one is
+    // canonical whatever the size and the encoding of the field. Fields of more
+    // than 128 bits hold it in a single element.
+    fn tag_elements(tag: u128) -> Vec<F> {
+        let limb_bits = F::CAPACITY.min(128);
+        let mask = u128::MAX >> (128 - limb_bits);
+        let two_64 = F::from(u64::MAX) + F::one();
+
+        (0..128)
+            .step_by(limb_bits as usize)
+            .map(|shift| {
+                let limb = (tag >> shift) & mask;
+                F::from((limb >> 64) as u64) * two_64 + F::from(limb as u64)
+            })
+            .collect()
     }
 
-    pub fn absorb(&mut self, x: &[F]) {
-        if x.len() == 0 {
-            return;
+    pub fn absorb(&mut self, x: &[F]) -> Result<(), SpongeError> {
+        if x.is_empty() {
+            return Ok(());
         }
 
+        self.check_op(SpongeOp::Absorb(x.len()))?;
+
         for x_i in x {
             if self.absorb_pos == self.rate {
                 self.permute();
                 self.absorb_pos = 0
             }
 
-            self.poseidon.state[self.absorb_pos] = *x_i;
+            let state = &mut self.permutation.state_mut()[self.capacity + self.absorb_pos];
+            match self.absorb_mode {
+                AbsorbMode::Overwrite => *state = *x_i,
+                AbsorbMode::Additive => *state += x_i,
+            }
             self.absorb_pos += 1;
         }
 
-        // TODO: Verify the IO pattern
         self.io_count += 1;
         self.squeeze_pos = self.rate;
+        Ok(())
     }
 
-    pub fn squeeze(&mut self, length: usize) -> Vec<F> {
+    pub fn squeeze(&mut self, length: usize) -> Result<Vec<F>, SpongeError> {
         let mut y = Vec::with_capacity(length);
         if length == 0 {
-            return vec![];
+            return Ok(vec![]);
         }
 
+        self.check_op(SpongeOp::Squeeze(length))?;
+
         for _ in 0..length {
             if self.squeeze_pos == self.rate {
                 self.permute();
@@ -164,66 +457,518 @@ impl<F: PrimeField<Repr = [u8; 32]>> PoseidonSponge<F> {
                 self.absorb_pos = 0;
             }
 
-            y.push(self.poseidon.state[self.squeeze_pos]);
+            y.push(self.permutation.state()[self.capacity + self.squeeze_pos]);
             self.squeeze_pos += 1;
         }
 
         self.io_count += 1;
-        y
+        Ok(y)
     }
 
-    pub fn finish(&self) -> Result<(), String> {
+    pub fn finish(&self) -> Result<(), SpongeError> {
+        if self.poisoned {
+            return Err(SpongeError::Poisoned);
+        }
+
         match self.io_pattern {
-            None => return Ok(()),
-            Some(ref io_pattern) => {
-                if self.io_count != io_pattern.0.len() {
-                    return Err("IO pattern mismatch".to_string());
-                }
+            None => Ok(()),
+            Some(ref io_pattern) if self.io_count < io_pattern.0.len() => {
+                Err(SpongeError::IncompletePattern {
+                    remaining: io_pattern.0.len() - self.io_count,
+                })
             }
+            Some(_) => Ok(()),
+        }
+    }
+
+    // Check the call against the next operation of the IO pattern, if there's
+    // one. A violation poisons the sponge: its state is erased, and every later
+    // call fails.
+    fn check_op(&mut self, op: SpongeOp) -> Result<(), SpongeError> {
+        if self.poisoned {
+            return Err(SpongeError::Poisoned);
+        }
+
+        let expected = match &self.io_pattern {
+            None => return Ok(()),
+            Some(io_pattern) => io_pattern.0.get(self.io_count).copied(),
+        };
+
+        if expected != Some(op) {
+            self.poisoned = true;
+            *self.permutation.state_mut() = [F::zero(); T];
+
+            return Err(SpongeError::UnexpectedOp {
+                expected,
+                found: op,
+            });
         }
 
         Ok(())
     }
 
     fn permute(&mut self) {
-        self.poseidon.permute();
-        self.poseidon.pos = 0;
+        self.permutation.permute();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::goldilocks::Goldilocks;
+    use crate::rescue::{self, RescuePrime};
+    use crate::spec::{Bn254, Pallas, Secq256k1, K256};
     //    use secq256k1::field::field_secq::FieldElement as Fp;
+    use ff::Field;
     use halo2curves::secp256k1::Fp;
+    use std::sync::Arc;
 
     #[test]
     fn test_interactive_protocol() {
-        let io_pattern = IOPattern(vec![
-            SpongeOp::Absorb(2),
-            SpongeOp::Squeeze(1),
-            SpongeOp::Absorb(1),
-            SpongeOp::Squeeze(3),
-        ]);
+        let io_pattern = IOPattern::new().absorb(2).squeeze(1).absorb(1).squeeze(3);
 
-        let io = vec![vec![Fp::from(1), Fp::from(2)], vec![Fp::from(3)]].concat();
+        let mut sponge = PoseidonSponge::<Fp>::construct(
+            b"test",
+            K256,
+            SpongePermutation::Poseidon,
+            AbsorbMode::Overwrite,
+            Some(io_pattern),
+        );
 
-        let mut sponge =
-            PoseidonSponge::construct(b"test", SpongeCurve::K256, Some(io_pattern.clone()));
+        sponge.absorb(&[Fp::from(1), Fp::from(2)]).unwrap();
+        sponge.squeeze(1).unwrap();
+        sponge.absorb(&[Fp::from(3)]).unwrap();
+        sponge.squeeze(3).unwrap();
 
-        let mut io_position = 0;
-        for op in io_pattern.0 {
-            match op {
-                SpongeOp::Absorb(l) => {
-                    sponge.absorb(&io[io_position..(io_position + l)]);
-                    io_position += l;
-                }
-                SpongeOp::Squeeze(l) => {
-                    sponge.squeeze(l);
-                }
-            }
+        assert_eq!(sponge.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_io_pattern_syntax() {
+        let io_pattern = IOPattern::new().absorb(5).absorb(1).squeeze(2).absorb(10);
+        assert_eq!(io_pattern.to_string(), "A5A1S2A10");
+        assert_eq!("A5A1S2A10".parse(), Ok(io_pattern.clone()));
+        assert_eq!(io_pattern.aggregate().to_string(), "A6S2A10");
+        assert_eq!("".parse(), Ok(IOPattern::new()));
+
+        for malformed in [
+            "A",
+            "S1A",
+            "B1",
+            "A1 S1",
+            "A+1",
+            "a1",
+            "Aé",
+            "S99999999999999999999",
+        ] {
+            assert_eq!(
+                malformed.parse::<IOPattern>(),
+                Err(SpongeError::Syntax(malformed.to_string()))
+            );
+        }
+
+        let json = serde_json::to_string(&io_pattern).unwrap();
+        assert_eq!(json, "\"A5A1S2A10\"");
+        assert_eq!(
+            serde_json::from_str::<IOPattern>(&json).unwrap(),
+            io_pattern
+        );
+        assert!(serde_json::from_str::<IOPattern>("\"A1X\"").is_err());
+    }
+
+    #[test]
+    fn test_io_pattern_violation() {
+        let io_pattern = IOPattern::new().absorb(2).squeeze(1);
+        let new_sponge = || {
+            PoseidonSponge::<Fp>::construct(
+                b"test",
+                K256,
+                SpongePermutation::Poseidon,
+                AbsorbMode::Overwrite,
+                Some(io_pattern.clone()),
+            )
+        };
+
+        // A wrong length poisons the sponge, and erases its state
+        let mut sponge = new_sponge();
+        assert_eq!(
+            sponge.absorb(&[Fp::from(1)]),
+            Err(SpongeError::UnexpectedOp {
+                expected: Some(SpongeOp::Absorb(2)),
+                found: SpongeOp::Absorb(1),
+            })
+        );
+        assert_eq!(sponge.permutation.state(), &[Fp::zero(); 3]);
+        assert_eq!(
+            sponge.absorb(&[Fp::from(1), Fp::from(2)]),
+            Err(SpongeError::Poisoned)
+        );
+        assert_eq!(sponge.squeeze(1), Err(SpongeError::Poisoned));
+        assert_eq!(sponge.finish(), Err(SpongeError::Poisoned));
+
+        // A wrong operation
+        let mut sponge = new_sponge();
+        assert_eq!(
+            sponge.squeeze(1),
+            Err(SpongeError::UnexpectedOp {
+                expected: Some(SpongeOp::Absorb(2)),
+                found: SpongeOp::Squeeze(1),
+            })
+        );
+
+        // An operation past the end of the pattern
+        let mut sponge = new_sponge();
+        sponge.absorb(&[Fp::from(1), Fp::from(2)]).unwrap();
+        sponge.squeeze(1).unwrap();
+        assert_eq!(
+            sponge.squeeze(1),
+            Err(SpongeError::UnexpectedOp {
+                expected: None,
+                found: SpongeOp::Squeeze(1),
+            })
+        );
+
+        // An incomplete pattern
+        let mut sponge = new_sponge();
+        sponge.absorb(&[Fp::from(1), Fp::from(2)]).unwrap();
+        assert_eq!(
+            sponge.finish(),
+            Err(SpongeError::IncompletePattern { remaining: 1 })
+        );
+    }
+
+    #[test]
+    fn test_absorb_mode() {
+        let new_sponge = |absorb_mode| {
+            PoseidonSponge::<Fp>::construct(
+                b"test",
+                K256,
+                SpongePermutation::Poseidon,
+                absorb_mode,
+                Some(IOPattern::new().absorb(2).squeeze(1).absorb(1)),
+            )
+        };
+
+        // The mode is committed in the tag
+        let mut overwrite = new_sponge(AbsorbMode::Overwrite);
+        let mut additive = new_sponge(AbsorbMode::Additive);
+        assert_ne!(overwrite.permutation.state(), additive.permutation.state());
+
+        // The absorption after a squeeze replaces the squeezed element, or is
+        // added to it
+        for sponge in [&mut overwrite, &mut additive] {
+            sponge.absorb(&[Fp::from(1), Fp::from(2)]).unwrap();
+        }
+        let squeezed = overwrite.squeeze(1).unwrap()[0];
+        overwrite.absorb(&[Fp::from(3)]).unwrap();
+        assert_eq!(overwrite.permutation.state()[1], Fp::from(3));
+
+        let squeezed_additive = additive.squeeze(1).unwrap()[0];
+        additive.absorb(&[Fp::from(3)]).unwrap();
+        assert_ne!(squeezed, squeezed_additive);
+        assert_eq!(
+            additive.permutation.state()[1],
+            squeezed_additive + Fp::from(3)
+        );
+    }
+
+    #[test]
+    fn test_capacity_first() {
+        // The tag is in the capacity, state[0], and the input goes into the rate
+        // after it in both modes, so absorbing never alters the tag
+        let io_pattern = IOPattern::new().absorb(2).squeeze(1);
+        for absorb_mode in [AbsorbMode::Overwrite, AbsorbMode::Additive] {
+            let mut sponge = PoseidonSponge::<Fp>::construct(
+                b"test",
+                K256,
+                SpongePermutation::Poseidon,
+                absorb_mode,
+                Some(io_pattern.clone()),
+            );
+            let tag = io_pattern.tag(b"test", absorb_mode).unwrap();
+            let tag =
+                Fp::from((tag >> 64) as u64) * Fp::from(1 << 32).square() + Fp::from(tag as u64);
+            assert_eq!(sponge.permutation.state()[0], tag);
+
+            sponge.absorb(&[Fp::from(1), Fp::from(2)]).unwrap();
+            assert_eq!(*sponge.permutation.state(), [tag, Fp::from(1), Fp::from(2)]);
         }
+    }
+
+    #[test]
+    fn test_invalid_rate() {
+        for rate in [0, 3] {
+            let poseidon =
+                Poseidon::new(<K256 as PoseidonSpec<Fp, 3>>::constants(), [Fp::zero(); 3]);
+            assert_eq!(
+                PoseidonSponge::try_new(b"test", poseidon, rate, AbsorbMode::Overwrite, None).err(),
+                Some(SpongeError::InvalidRate { rate, width: 3 })
+            );
+        }
+    }
 
+    #[test]
+    fn test_invalid_pattern() {
+        let tag = |io_pattern: IOPattern| io_pattern.tag(b"test", AbsorbMode::Additive);
+
+        assert_eq!(
+            tag(IOPattern::new().absorb(1).squeeze(0)),
+            Err(SpongeError::EmptyOp)
+        );
+        assert_eq!(
+            tag(IOPattern::new().absorb(1 << 31)),
+            Err(SpongeError::OversizedOp { length: 1 << 31 })
+        );
+        // Consecutive operations of the same kind are encoded in one word
+        assert!(tag(IOPattern::new().squeeze(MAX_OP_LENGTH)).is_ok());
+        assert_eq!(
+            tag(IOPattern::new().squeeze(MAX_OP_LENGTH).squeeze(1)),
+            Err(SpongeError::OversizedOp { length: 1 << 31 })
+        );
+
+        // The tag takes three elements of the Goldilocks field
+        let rescue = RescuePrime::new(rescue::constants(), [Goldilocks::zero(); rescue::WIDTH]);
+        assert_eq!(
+            PoseidonSponge::try_new(b"test", rescue, 10, AbsorbMode::Additive, None).err(),
+            Some(SpongeError::InsufficientCapacity {
+                capacity: 2,
+                required: 3
+            })
+        );
+
+        // Nor does building a sponge from a parameter set panic
+        let try_construct = |io_pattern, permutation| {
+            PoseidonSponge::<Fp, 5>::try_construct(
+                b"test",
+                K256,
+                permutation,
+                AbsorbMode::Overwrite,
+                Some(io_pattern),
+            )
+            .err()
+        };
+        assert_eq!(
+            try_construct(IOPattern::new().absorb(0), SpongePermutation::Poseidon),
+            Some(SpongeError::EmptyOp)
+        );
+        assert_eq!(
+            try_construct(IOPattern::new().absorb(1), SpongePermutation::Poseidon2),
+            Some(SpongeError::Poseidon2(Poseidon2Error::UnsupportedWidth(5)))
+        );
+    }
+
+    fn check_width<const T: usize>() {
+        let rate = T - 1;
+        let io_pattern = IOPattern::new().absorb(rate).squeeze(1);
+        let input: Vec<Fp> = (1..=rate as u64).map(Fp::from).collect();
+
+        let mut sponge = PoseidonSponge::<Fp, T>::construct(
+            b"test",
+            K256,
+            SpongePermutation::Poseidon,
+            AbsorbMode::Overwrite,
+            Some(io_pattern),
+        );
+        assert_eq!(sponge.rate, rate);
+        let mut state = *sponge.permutation.state();
+
+        sponge.absorb(&input).unwrap();
+        let output = sponge.squeeze(1).unwrap();
+        assert_eq!(sponge.finish(), Ok(()));
+
+        // A full absorption overwrites the whole rate portion of the state,
+        // after the tag in the capacity
+        state[1..].copy_from_slice(&input);
+        let mut poseidon = Poseidon::new(K256::constants(), state);
+        poseidon.permute();
+
+        assert_eq!(output, vec![poseidon.state[1]]);
+    }
+
+    #[test]
+    fn test_width() {
+        check_width::<2>();
+        check_width::<5>();
+        check_width::<9>();
+    }
+
+    #[test]
+    fn test_poseidon2() {
+        let io_pattern = IOPattern::new().absorb(2).squeeze(1);
+        let input = vec![Fp::from(1), Fp::from(2)];
+
+        let mut sponge = PoseidonSponge::<Fp>::construct(
+            b"test",
+            K256,
+            SpongePermutation::Poseidon2,
+            AbsorbMode::Overwrite,
+            Some(io_pattern),
+        );
+
+        let tag = sponge.permutation.state()[0];
+
+        sponge.absorb(&input).unwrap();
+        let output = sponge.squeeze(1).unwrap();
         assert_eq!(sponge.finish(), Ok(()));
+
+        let state = [tag, Fp::from(1), Fp::from(2)];
+        let mut poseidon2 = Poseidon2::new(K256::poseidon2_constants().unwrap(), state);
+        poseidon2.permute();
+
+        assert_eq!(output, vec![poseidon2.state[1]]);
+    }
+
+    // K256 under another type, so that no other test holds its constants
+    struct SharedK256;
+
+    impl PoseidonSpec<Fp, 3> for SharedK256 {
+        fn num_full_rounds() -> usize {
+            <K256 as PoseidonSpec<Fp, 3>>::num_full_rounds()
+        }
+
+        fn num_partial_rounds() -> usize {
+            <K256 as PoseidonSpec<Fp, 3>>::num_partial_rounds()
+        }
+
+        fn alpha() -> u64 {
+            <K256 as PoseidonSpec<Fp, 3>>::alpha()
+        }
+
+        fn generate_constants() -> (Vec<Fp>, [[Fp; 3]; 3]) {
+            <K256 as PoseidonSpec<Fp, 3>>::generate_constants()
+        }
+    }
+
+    #[test]
+    fn test_constants_are_shared() {
+        let constants = SharedK256::constants();
+        let sponges: Vec<PoseidonSponge<Fp>> = (0..2)
+            .map(|_| {
+                PoseidonSponge::construct(
+                    b"test",
+                    SharedK256,
+                    SpongePermutation::Poseidon,
+                    AbsorbMode::Overwrite,
+                    None,
+                )
+            })
+            .collect();
+
+        // The cache, this test and each sponge
+        assert_eq!(Arc::strong_count(&constants), 4);
+        drop(sponges);
+        assert_eq!(Arc::strong_count(&constants), 2);
+    }
+
+    #[test]
+    fn test_rescue_prime() {
+        let state = [Goldilocks::zero(); rescue::WIDTH];
+        let io_pattern = IOPattern::new().absorb(rescue::RATE).squeeze(1);
+        let input: Vec<Goldilocks> = (1..=rescue::RATE as u64).map(Goldilocks::from).collect();
+
+        let mut sponge = PoseidonSponge::new(
+            b"test",
+            RescuePrime::new(rescue::constants(), state),
+            rescue::RATE,
+            AbsorbMode::Overwrite,
+            Some(io_pattern.clone()),
+        );
+        assert_eq!(sponge.capacity, rescue::CAPACITY);
+
+        // The 128-bit tag takes three elements of 63 bits
+        let tag = io_pattern.tag(b"test", AbsorbMode::Overwrite).unwrap();
+        let mut state = *sponge.permutation.state();
+        let limbs = [0, 63, 126].map(|shift| (tag >> shift) as u64 & (u64::MAX >> 1));
+        assert_eq!(state[..3], limbs.map(Goldilocks::from));
+        assert_eq!(state[3], Goldilocks::zero());
+
+        sponge.absorb(&input).unwrap();
+        let output = sponge.squeeze(1).unwrap();
+        assert_eq!(sponge.finish(), Ok(()));
+
+        state[rescue::CAPACITY..].copy_from_slice(&input);
+        let mut rescue = RescuePrime::new(rescue::constants(), state);
+        rescue.permute();
+
+        assert_eq!(output, vec![rescue.state[rescue::CAPACITY]]);
+    }
+
+    #[test]
+    fn test_bn254() {
+        use halo2curves::bn256::Fr;
+
+        // poseidon([1, 2]) from the circomlibjs tests
+        let mut poseidon = Poseidon::new(Bn254::constants(), [Fr::zero(); 3]);
+        assert_eq!(
+            poseidon.hash(&[Fr::from(1), Fr::from(2)]),
+            Fr::from_str_vartime(
+                "7853200120776062878684798364095072458815029376092732009249414926327459813530"
+            )
+            .unwrap()
+        );
+
+        let io_pattern = IOPattern::new().absorb(2).squeeze(1);
+        let mut sponge = PoseidonSponge::<Fr>::construct(
+            b"test",
+            Bn254,
+            SpongePermutation::Poseidon,
+            AbsorbMode::Overwrite,
+            Some(io_pattern),
+        );
+
+        let tag = sponge.permutation.state()[0];
+
+        sponge.absorb(&[Fr::from(1), Fr::from(2)]).unwrap();
+        let output = sponge.squeeze(1).unwrap();
+        assert_eq!(sponge.finish(), Ok(()));
+
+        poseidon.state = [tag, Fr::from(1), Fr::from(2)];
+        poseidon.permute();
+        assert_eq!(output, vec![poseidon.state[1]]);
+    }
+
+    #[test]
+    fn test_pasta() {
+        use halo2curves::pasta::Fp;
+
+        // Hash<_, P128Pow5T3, ConstantLength<2>, 3, 2> of [1, 2]
+        let mut poseidon = Poseidon::new(Pallas::constants(), [Fp::zero(); 3]);
+        assert_eq!(
+            poseidon.hash(&[Fp::from(1), Fp::from(2)]),
+            Fp::from_str_vartime(
+                "24123908145095057026791623326467558304806014471451005010637196320467268264780"
+            )
+            .unwrap()
+        );
+
+        let io_pattern = IOPattern::new().absorb(2).squeeze(1);
+        let mut sponge = PoseidonSponge::<Fp>::construct(
+            b"test",
+            Pallas,
+            SpongePermutation::Poseidon,
+            AbsorbMode::Overwrite,
+            Some(io_pattern),
+        );
+
+        sponge.absorb(&[Fp::from(1), Fp::from(2)]).unwrap();
+        sponge.squeeze(1).unwrap();
+        assert_eq!(sponge.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_secq256k1() {
+        use halo2curves::secp256k1::Fq;
+
+        // Poseidon over the scalar field of secp256k1. No other implementation
+        // publishes constants for this field, so the digest is a regression
+        // value of the constants generated as by the hadeshash scripts.
+        let mut poseidon = Poseidon::new(Secq256k1::constants(), [Fq::zero(); 3]);
+        assert_eq!(
+            poseidon.hash(&[Fq::from(1), Fq::from(2)]),
+            Fq::from_str_vartime(
+                "54619696839718512362936560208906379782274656825832257531904619740236370598977"
+            )
+            .unwrap()
+        );
     }
 }
@@ -1,65 +1,312 @@
-use crate::sponge::{PoseidonSponge, SpongeCurve};
+use crate::permutation::{BoxedPermutation, Permutation};
+use crate::spec::PoseidonSpec;
+use crate::sponge::{AbsorbMode, PoseidonSponge, SpongeError, SpongePermutation};
 use ff::PrimeField;
-use halo2curves::{CurveAffineExt, FieldExt};
+use halo2curves::{CurveAffine, CurveAffineExt, FieldExt};
+use std::fmt;
+use std::marker::PhantomData;
 
-pub struct PoseidonTranscript<C: CurveAffineExt> {
-    sponge: PoseidonSponge<C::ScalarExt>,
+// The longest byte array that is appended as a single element
+const MAX_BYTES: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TranscriptError {
+    OversizedInput { length: usize, max: usize },
+    // The identity has no affine coordinates to append
+    IdentityPoint,
+    NonCanonical,
+    Sponge(SpongeError),
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranscriptError::OversizedInput { length, max } => write!(
+                f,
+                "{} bytes can't be appended at once, the maximum is {}",
+                length, max
+            ),
+            TranscriptError::IdentityPoint => {
+                write!(f, "the identity point can't be appended")
+            }
+            TranscriptError::NonCanonical => {
+                write!(f, "the bytes are not a canonical scalar encoding")
+            }
+            TranscriptError::Sponge(err) => write!(f, "{}", err),
+        }
+    }
 }
 
-impl<C> PoseidonTranscript<C>
+impl std::error::Error for TranscriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TranscriptError::Sponge(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<SpongeError> for TranscriptError {
+    fn from(err: SpongeError) -> Self {
+        TranscriptError::Sponge(err)
+    }
+}
+
+pub struct PoseidonTranscript<
+    C: CurveAffineExt,
+    const T: usize = 3,
+    P: Permutation<C::ScalarExt, T> = BoxedPermutation<<C as CurveAffine>::ScalarExt, T>,
+> {
+    sponge: PoseidonSponge<C::ScalarExt, T, P>,
+    _curve: PhantomData<C>,
+}
+
+impl<C, const T: usize> PoseidonTranscript<C, T>
 where
     C: CurveAffineExt,
     C::ScalarExt: FieldExt<Repr = [u8; 32]>,
     C::Base: FieldExt<Repr = [u8; 32]>,
 {
-    pub fn new(domain_separator: &[u8], curve: SpongeCurve) -> Self {
-        // The scalar field of the curve specified by the generic argument
-        // is used as the finite field of the Poseidon sponge.
-        match curve {
-            SpongeCurve::K256 => {
-                assert_eq!(
-                    C::ScalarExt::MODULUS,
-                    "0xfffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f"
-                );
-            }
-        }
+    // The parameter set must be over the scalar field of the curve, and the
+    // absorb mode is committed in the tag of the sponge. Panics if the sponge
+    // can't be built, see `PoseidonSponge::try_construct`.
+    pub fn new<S: PoseidonSpec<C::ScalarExt, T>>(
+        domain_separator: &[u8],
+        spec: S,
+        permutation: SpongePermutation,
+        absorb_mode: AbsorbMode,
+    ) -> Self {
+        Self::try_new(domain_separator, spec, permutation, absorb_mode)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_new<S: PoseidonSpec<C::ScalarExt, T>>(
+        domain_separator: &[u8],
+        spec: S,
+        permutation: SpongePermutation,
+        absorb_mode: AbsorbMode,
+    ) -> Result<Self, TranscriptError> {
+        let sponge =
+            PoseidonSponge::try_construct(domain_separator, spec, permutation, absorb_mode, None)?;
+
+        Ok(Self::from_sponge(sponge))
+    }
+}
 
+impl<C, const T: usize, P> PoseidonTranscript<C, T, P>
+where
+    C: CurveAffineExt,
+    C::ScalarExt: FieldExt<Repr = [u8; 32]>,
+    C::Base: FieldExt<Repr = [u8; 32]>,
+    P: Permutation<C::ScalarExt, T>,
+{
+    // A transcript over a sponge with any permutation. If the sponge follows an
+    // IO pattern, the calls that violate it fail.
+    pub fn from_sponge(sponge: PoseidonSponge<C::ScalarExt, T, P>) -> Self {
         Self {
-            sponge: PoseidonSponge::construct(domain_separator, curve, None),
+            sponge,
+            _curve: PhantomData,
         }
     }
 
-    // Append a byte array to the transcript.
-    pub fn append_bytes(&mut self, bytes: &[u8]) {
-        assert!(bytes.len() <= 64);
-        let mut padded_bytes = Vec::with_capacity(64);
-        padded_bytes.extend_from_slice(bytes);
-        padded_bytes.resize(64, 0);
-        self.sponge.absorb(&[C::ScalarExt::from_bytes_wide(
-            padded_bytes.as_slice().try_into().unwrap(),
-        )]);
+    // Append a byte array of at most 64 bytes to the transcript.
+    pub fn append_bytes(&mut self, bytes: &[u8]) -> Result<(), TranscriptError> {
+        if bytes.len() > MAX_BYTES {
+            return Err(TranscriptError::OversizedInput {
+                length: bytes.len(),
+                max: MAX_BYTES,
+            });
+        }
+
+        let mut padded_bytes = [0u8; MAX_BYTES];
+        padded_bytes[..bytes.len()].copy_from_slice(bytes);
+        self.sponge
+            .absorb(&[C::ScalarExt::from_bytes_wide(&padded_bytes)])?;
+        Ok(())
     }
 
-    // Append a group element to the transcript.
-    pub fn append_point(&mut self, point: &C) {
-        let coords = point.coordinates().unwrap();
+    // Append a group element other than the identity to the transcript.
+    pub fn append_point(&mut self, point: &C) -> Result<(), TranscriptError> {
+        let coords = point.coordinates();
+        if bool::from(coords.is_none()) {
+            return Err(TranscriptError::IdentityPoint);
+        }
+        let coords = coords.unwrap();
         let x = coords.x();
         let y = coords.y();
 
         let x: [u8; 32] = x.to_repr();
         let y = y.to_repr();
 
-        self.append_bytes(&x);
-        self.append_bytes(&y);
+        self.append_bytes(&x)?;
+        self.append_bytes(&y)
     }
 
     // Append a scalar field element to the transcript.
-    pub fn append_scalar(&mut self, fe: &C::ScalarExt) {
-        self.sponge.absorb(&[*fe]);
+    pub fn append_scalar(&mut self, fe: &C::ScalarExt) -> Result<(), TranscriptError> {
+        self.sponge.absorb(&[*fe])?;
+        Ok(())
+    }
+
+    // Append a scalar field element from its encoding, which must be canonical.
+    pub fn append_scalar_repr(&mut self, repr: [u8; 32]) -> Result<(), TranscriptError> {
+        let fe =
+            Option::from(C::ScalarExt::from_repr(repr)).ok_or(TranscriptError::NonCanonical)?;
+        self.append_scalar(&fe)
     }
 
     // Squeeze a vector of scalar field elements from the transcript.
-    pub fn squeeze(&mut self, length: usize) -> Vec<C::ScalarExt> {
-        self.sponge.squeeze(length)
+    pub fn squeeze(&mut self, length: usize) -> Result<Vec<C::ScalarExt>, TranscriptError> {
+        Ok(self.sponge.squeeze(length)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poseidon2::Poseidon2Error;
+    use crate::spec::{Secq256k1, K256};
+    use crate::sponge::{IOPattern, SpongeOp};
+    use halo2curves::group::prime::PrimeCurveAffine;
+    use halo2curves::secp256k1::{Fp, Fq, Secp256k1Affine};
+    use halo2curves::secq256k1::Secq256k1Affine;
+
+    fn squeeze_challenge<C, S>(spec: S, point: C) -> C::ScalarExt
+    where
+        C: CurveAffineExt,
+        C::ScalarExt: FieldExt<Repr = [u8; 32]>,
+        C::Base: FieldExt<Repr = [u8; 32]>,
+        S: PoseidonSpec<C::ScalarExt, 3>,
+    {
+        let mut transcript = PoseidonTranscript::<C>::new(
+            b"test",
+            spec,
+            SpongePermutation::Poseidon,
+            AbsorbMode::Additive,
+        );
+
+        transcript.append_point(&point).unwrap();
+        transcript.append_scalar(&C::ScalarExt::from(1)).unwrap();
+        transcript.squeeze(1).unwrap()[0]
+    }
+
+    #[test]
+    fn test_absorb_mode() {
+        // The mode is part of the tag, so the challenges differ even before
+        // the first absorption
+        let challenge = |absorb_mode| {
+            let mut transcript = PoseidonTranscript::<Secq256k1Affine>::new(
+                b"test",
+                K256,
+                SpongePermutation::Poseidon,
+                absorb_mode,
+            );
+            transcript.squeeze(1).unwrap()[0]
+        };
+        assert_ne!(
+            challenge(AbsorbMode::Additive),
+            challenge(AbsorbMode::Overwrite)
+        );
+    }
+
+    #[test]
+    fn test_secp_secq_cycle() {
+        // The scalar field of secq256k1 is the base field of secp256k1, and
+        // vice versa. The points are the generator of secp256k1 and the point
+        // of secq256k1 with x = 1, and the challenges are regression values,
+        // reproduced by a separate port of the transcript.
+        let point = Secq256k1Affine::from_xy(
+            Fq::from(1),
+            Fq::from_str_vartime(
+                "5647885500061325675748484062311156374277086380342947163834798608016077912256",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            squeeze_challenge(K256, point),
+            Fp::from_str_vartime(
+                "76434327774845064453981647583593380717215811755640468241550226403608278340892"
+            )
+            .unwrap()
+        );
+
+        let generator = Secp256k1Affine::from_xy(
+            Fp::from_str_vartime(
+                "55066263022277343669578718895168534326250603453777594175500187360389116729240",
+            )
+            .unwrap(),
+            Fp::from_str_vartime(
+                "32670510020758816978083085130507043184471273380659243275938904335757337482424",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(generator, Secp256k1Affine::generator());
+        assert_eq!(
+            squeeze_challenge(Secq256k1, generator),
+            Fq::from_str_vartime(
+                "10470416429794883081996112779080391188569155985672437021893290252434991440343"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let mut transcript = PoseidonTranscript::<Secq256k1Affine>::new(
+            b"test",
+            K256,
+            SpongePermutation::Poseidon,
+            AbsorbMode::Additive,
+        );
+
+        assert_eq!(
+            transcript.append_bytes(&[0; 65]),
+            Err(TranscriptError::OversizedInput {
+                length: 65,
+                max: 64
+            })
+        );
+        assert_eq!(
+            transcript.append_point(&Secq256k1Affine::identity()),
+            Err(TranscriptError::IdentityPoint)
+        );
+        assert_eq!(
+            transcript.append_scalar_repr([0xff; 32]),
+            Err(TranscriptError::NonCanonical)
+        );
+        assert_eq!(transcript.append_scalar_repr([0; 32]), Ok(()));
+
+        // Poseidon2 isn't defined for the width 5
+        assert_eq!(
+            PoseidonTranscript::<Secq256k1Affine, 5>::try_new(
+                b"test",
+                K256,
+                SpongePermutation::Poseidon2,
+                AbsorbMode::Additive
+            )
+            .err(),
+            Some(TranscriptError::Sponge(SpongeError::Poseidon2(
+                Poseidon2Error::UnsupportedWidth(5)
+            )))
+        );
+
+        // The sponge's IO pattern is enforced through the transcript
+        let sponge = PoseidonSponge::construct(
+            b"test",
+            K256,
+            SpongePermutation::Poseidon,
+            AbsorbMode::Overwrite,
+            Some(IOPattern::new().absorb(1)),
+        );
+        let mut transcript = PoseidonTranscript::<Secq256k1Affine>::from_sponge(sponge);
+        assert_eq!(
+            transcript.squeeze(1),
+            Err(TranscriptError::Sponge(SpongeError::UnexpectedOp {
+                expected: Some(SpongeOp::Absorb(1)),
+                found: SpongeOp::Squeeze(1),
+            }))
+        );
     }
 }